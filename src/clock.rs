@@ -0,0 +1,22 @@
+/// Monotonic time source supplied by the application, used to schedule transmissions and
+/// timestamp received packets. Typically backed by a hardware timer or `std::time::Instant`.
+pub trait Clock {
+    /// Returns the current time in microseconds. Must never go backwards; the epoch is up to
+    /// the implementation.
+    fn now_us(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_us(&self) -> u64 {
+        (**self).now_us()
+    }
+}
+
+/// A received packet together with the instant it arrived.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimestampedPacket<const MTU: usize = 255> {
+    pub payload: heapless::Vec<u8, MTU>,
+    /// When `RxDone` was raised, in microseconds of the `Clock` passed to the read. Only an upper
+    /// bound when the driver had to find the packet by polling.
+    pub rx_done_us: u64,
+}
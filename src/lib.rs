@@ -1,4 +1,4 @@
-#![cfg_attr(not(feature = "mock"), no_std)]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 #![crate_type = "lib"]
 #![crate_name = "embedded_radio"]
@@ -47,7 +47,7 @@
 //!         spi, cs, reset,  FREQUENCY, &mut Delay)
 //!         .expect("Failed to communicate with radio module!");
 //!
-//!     lora.set_tx_power(17,1); //Using PA_BOOST. See your board for correct pin.
+//!     lora.set_tx_power(17, sx127x_lora::PaSelect::PaBoost); //See your board for correct pin.
 //!
 //!     let message = "Hello, world!";
 //!     let mut buffer = [0;255];
@@ -62,6 +62,15 @@
 //!     }
 //! }
 //! ```
+//! ## Linux
+//! With the `linux` feature, `LoRa::open_linux` opens the SPI device and requests the GPIO lines through the
+//! character device, replacing the setup above and the deprecated sysfs GPIO interface. Chip select is driven
+//! from a free GPIO line, as the kernel keeps the CE lines of the SPI controller.
+//! ```ignore
+//! let mut lora = embedded_radio::LoRa::open_linux("/dev/spidev0.0", 25, 21, Some(24), Config::new(915))
+//!     .expect("Failed to communicate with radio module!");
+//! lora.transmit_payload(b"Hello, world!").unwrap();
+//! ```
 //! ## STM32F429 Blocking Receive
 //! Utilizes a STM32F429 to receive data using the blocking `poll_irq(timeout)` function. It prints
 //! the received packet back out over semihosting. The example utilizes the `stm32f429_hal`, `cortex_m`,
@@ -139,18 +148,54 @@
 //! }
 //! ```
 //! ## Interrupts
-//! The crate currently polls the IRQ register on the radio to determine if a new packet has arrived. This
-//! would be more efficient if instead an interrupt was connect the the module's DIO_0 pin. Once interrupt
-//! support is available in `embedded-hal`, then this will be added. It is possible to implement this function on a
-//! device-to-device basis by retrieving a packet with the `read_packet()` function.
+//! Connect the module's DIO pins to interrupt lines and route the events you need to them with
+//! `set_dio_mapping()`, e.g. `LoRaDio0::RxDone` or `LoRaDio0::TxDone`. From the interrupt handler,
+//! or after waking from it, call `handle_interrupt()`: it reads and clears the IRQ flags once and returns
+//! the decoded `RadioEvents`. A packet announced by `RadioEvent::RxDone` is then fetched with `read_packet()`.
+//! `enable_interrupt()` maps a pin and unmasks its IRQ source in one go, and `set_enabled_irqs()` masks the
+//! sources you do not need so the DIO lines only fire for the selected events.
+//! Without interrupts, `read_packet()` and `transmitting()` poll the IRQ register over SPI instead.
+//! ## Separate send and receive tasks
+//! Wrap a radio, `LoRa` or `MockLora`, in a `SharedRadio` and `split()` it into a `RadioSender` and a
+//! `RadioReceiver`, which can be moved to different tasks or interrupt handlers. The halves take turns on the
+//! half duplex radio, so polling for packets never cuts off a transmission.
+
+mod clock;
+pub use clock::{Clock, TimestampedPacket};
 
 mod radio_traits;
-pub use radio_traits::EmbeddedRadio;
+pub use radio_traits::{
+    AsyncEmbeddedRadio, Capabilities, ConfigurableRadio, EmbeddedRadio, ErasedRadio, LoRaDataRate,
+    OversizedPayload, PaSelect, RadioError, RadioErrorKind,
+};
+
+mod split;
+pub use split::{RadioReceiver, RadioSender, SharedRadio, SplitError};
 
 #[cfg(feature = "sx127x_lora")]
 mod sx127x_lora;
 #[cfg(feature = "sx127x_lora")]
-pub use sx127x_lora::LoRa;
+pub use sx127x_lora::{
+    dio, AntennaMode, AntennaSwitch, Config, Fault, FskDataModulationShaping, HeaderMode, Health,
+    IRQMask, LnaGain, LoRa, ModemStatus, NoPin, NoSwitch, Oscillator, PaRamp, Pins, RadioEvent,
+    RadioEvents, RadioEventsIter, RfSwitch, RfSwitchError,
+};
+
+#[cfg(all(feature = "sx127x_lora", feature = "async"))]
+pub use sx127x_lora::AsyncLoRa;
+
+#[cfg(feature = "linux")]
+pub use sx127x_lora::{LinuxError, LinuxLoRa, GPIO_CHIP, SPI_SPEED_HZ};
+
+#[cfg(feature = "service")]
+mod service;
+#[cfg(feature = "service")]
+pub use service::{RadioService, ReceivedPacket, ServiceError, ServiceHandle, ServiceOptions};
+
+#[cfg(feature = "tokio")]
+mod tokio_radio;
+#[cfg(feature = "tokio")]
+pub use tokio_radio::{EdgeSource, NoEdges, Packets, TokioRadio, TokioRadioError};
 
 #[cfg(feature = "mock")]
 mod radio_mock;
@@ -1,30 +1,84 @@
-use crate::EmbeddedRadio;
-use crossbeam::channel;
-use embedded_hal::blocking::delay::DelayMs;
+use crate::radio_traits::LORA_BANDWIDTHS;
+use crate::{
+    AsyncEmbeddedRadio, Capabilities, Clock, ConfigurableRadio, EmbeddedRadio, LoRaDataRate,
+    OversizedPayload, PaSelect, RadioError, RadioErrorKind, TimestampedPacket,
+};
+use async_channel as channel;
+use core::ops::RangeInclusive;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub enum LoraError<RX, TX> {
     Receiver(RX),
     Transmitter(TX),
+    PayloadTooLarge(usize),
+    Unsupported,
 }
 
-type RadioBuffer = heapless::Vec<u8, 255>;
+impl<RX: std::fmt::Debug, TX: std::fmt::Debug> RadioError for LoraError<RX, TX> {
+    fn kind(&self) -> RadioErrorKind {
+        match self {
+            LoraError::Receiver(_) | LoraError::Transmitter(_) => RadioErrorKind::Disconnected,
+            LoraError::PayloadTooLarge(_) => RadioErrorKind::InvalidPayload,
+            LoraError::Unsupported => RadioErrorKind::InvalidSetting,
+        }
+    }
+}
+
+impl<RX, TX> From<OversizedPayload> for LoraError<RX, TX> {
+    fn from(payload: OversizedPayload) -> Self {
+        LoraError::PayloadTooLarge(payload.0)
+    }
+}
+
+type RadioBuffer<const MTU: usize> = heapless::Vec<u8, MTU>;
+
+/// A packet on air.
+#[derive(Clone, Debug)]
+pub struct Frame<const MTU: usize> {
+    payload: RadioBuffer<MTU>,
+    /// When it was sent, if the radios share a clock.
+    sent_us: Option<u64>,
+    /// Only radios tuned to the same frequency receive it.
+    frequency_hz: u32,
+}
 
-pub struct MockLora {
-    rx: channel::Receiver<RadioBuffer>,
-    tx: Vec<channel::Sender<RadioBuffer>>,
+/// Simulated radios connected by channels. `MTU` is the largest payload they carry.
+pub struct MockLora<const MTU: usize = 255> {
+    rx: channel::Receiver<Frame<MTU>>,
+    tx: Vec<channel::Sender<Frame<MTU>>>,
+    clock: Option<Arc<dyn Clock + Send + Sync>>,
+    frequency_hz: u32,
+    pa_select: PaSelect,
+    tx_power_dbm: i8,
+    data_rate: LoRaDataRate,
+    bandwidth: u32,
 }
 
 impl MockLora {
     pub fn new(num_radios: usize) -> Vec<MockLora> {
+        Self::build(num_radios, None)
+    }
+
+    /// Like `new`, with every packet stamped on `clock` when it is sent, which is reported as its
+    /// arrival time by `read_packet_timestamped`.
+    pub fn with_clock(num_radios: usize, clock: Arc<dyn Clock + Send + Sync>) -> Vec<MockLora> {
+        Self::build(num_radios, Some(clock))
+    }
+}
+
+impl<const MTU: usize> MockLora<MTU> {
+    /// Builds radios with any `MTU`, e.g. `MockLora::<64>::build(3, None)`, optionally stamping packets on a
+    /// shared clock as `with_clock` does.
+    pub fn build(num_radios: usize, clock: Option<Arc<dyn Clock + Send + Sync>>) -> Vec<Self> {
         let radios_antennas: Vec<(_, _)> = (0..num_radios)
-            .map(|_| channel::unbounded::<RadioBuffer>())
+            .map(|_| channel::unbounded::<Frame<MTU>>())
             .enumerate()
             .collect();
 
         let radios_antennas_reference = radios_antennas.clone();
 
-        let lora_modules: Vec<MockLora> = radios_antennas
+        let lora_modules: Vec<Self> = radios_antennas
             .iter()
             .map(|self_antenna| {
                 let txs = radios_antennas_reference
@@ -36,26 +90,109 @@ impl MockLora {
                 MockLora {
                     rx: self_antenna.1 .1.clone(),
                     tx: txs,
+                    clock: clock.clone(),
+                    frequency_hz: 915_000_000,
+                    pa_select: PaSelect::PaBoost,
+                    tx_power_dbm: 17,
+                    data_rate: LoRaDataRate {
+                        spreading_factor: 7,
+                        coding_rate: 5,
+                    },
+                    bandwidth: 125_000,
                 }
             })
             .collect();
 
         lora_modules
     }
-}
 
-impl EmbeddedRadio for MockLora {
-    type Error = LoraError<channel::RecvError, channel::SendError<RadioBuffer>>;
+    /// Reads a packet as `read_packet` does, along with the instant it was sent if the radios were
+    /// built `with_clock`, or the current time on `clock` otherwise.
+    // same error as the `EmbeddedRadio` methods, which carries the unsent frame
+    #[allow(clippy::result_large_err)]
+    pub fn read_packet_timestamped<C: Clock>(
+        &mut self,
+        clock: &C,
+    ) -> Result<Option<TimestampedPacket<MTU>>, <Self as EmbeddedRadio<MTU>>::Error> {
+        Ok(self.receive()?.map(|frame| TimestampedPacket {
+            payload: frame.payload,
+            rx_done_us: frame.sent_us.unwrap_or_else(|| clock.now_us()),
+        }))
+    }
 
-    fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
-        let mut buffer: RadioBuffer = heapless::Vec::new();
+    /// Returns the carrier frequency in Hz.
+    pub fn frequency_hz(&self) -> u32 {
+        self.frequency_hz
+    }
+
+    /// Returns the output power in dBm.
+    pub fn tx_power_dbm(&self) -> i8 {
+        self.tx_power_dbm
+    }
+
+    /// Returns the spreading factor and coding rate.
+    pub fn data_rate(&self) -> LoRaDataRate {
+        self.data_rate
+    }
+
+    /// Selects the power amplifier, which `tx_power_range` depends on, as `Config::pa_select` does for
+    /// the real radio. Defaults to `PaSelect::PaBoost`.
+    pub fn set_pa_select(&mut self, pa_select: PaSelect) {
+        self.pa_select = pa_select;
+    }
+
+    /// Returns the channel bandwidth in Hz.
+    pub fn bandwidth(&self) -> u32 {
+        self.bandwidth
+    }
 
-        for &payload_byte in payload.iter().take(255) {
-            buffer.push(payload_byte).unwrap();
+    #[allow(clippy::result_large_err)]
+    fn receive(&mut self) -> Result<Option<Frame<MTU>>, <Self as EmbeddedRadio<MTU>>::Error> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(frame) if frame.frequency_hz == self.frequency_hz => return Ok(Some(frame)),
+                // sent on another channel
+                Ok(_) => continue,
+                Err(channel::TryRecvError::Empty) => return Ok(None),
+                Err(channel::TryRecvError::Closed) => {
+                    return Err(LoraError::Receiver(channel::RecvError))
+                }
+            }
+        }
+    }
+
+    /// Builds the frame carrying the concatenation of `payloads`.
+    #[allow(clippy::result_large_err)]
+    fn frame(&self, payloads: &[&[u8]]) -> Result<Frame<MTU>, <Self as EmbeddedRadio<MTU>>::Error> {
+        let len: usize = payloads.iter().map(|payload| payload.len()).sum();
+        let mut buffer: RadioBuffer<MTU> = heapless::Vec::new();
+
+        for payload in payloads {
+            buffer
+                .extend_from_slice(payload)
+                .map_err(|_| LoraError::PayloadTooLarge(len))?;
         }
 
+        Ok(Frame {
+            payload: buffer,
+            sent_us: self.clock.as_ref().map(|clock| clock.now_us()),
+            frequency_hz: self.frequency_hz,
+        })
+    }
+}
+
+impl<const MTU: usize> EmbeddedRadio<MTU> for MockLora<MTU> {
+    type Error = LoraError<channel::RecvError, channel::SendError<Frame<MTU>>>;
+
+    fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.transmit_vectored(&[payload])
+    }
+
+    fn transmit_vectored(&mut self, payloads: &[&[u8]]) -> Result<(), Self::Error> {
+        let frame = self.frame(payloads)?;
         for tx in self.tx.iter() {
-            tx.send(buffer.clone()).map_err(Self::Error::Transmitter)?;
+            tx.send_blocking(frame.clone())
+                .map_err(Self::Error::Transmitter)?;
         }
 
         Ok(())
@@ -72,39 +209,99 @@ impl EmbeddedRadio for MockLora {
         Ok(false)
     }
 
-    fn read_packet(&mut self) -> Result<Option<RadioBuffer>, Self::Error> {
-        match self.rx.try_recv() {
-            Ok(value) => Ok(Some(value)),
-            Err(channel::TryRecvError::Empty) => Ok(None),
-            Err(channel::TryRecvError::Disconnected) => {
-                Err(Self::Error::Receiver(channel::RecvError))
-            }
+    fn read_packet(&mut self) -> Result<Option<RadioBuffer<MTU>>, Self::Error> {
+        Ok(self.receive()?.map(|frame| frame.payload))
+    }
+
+    /// Full duplex, as the channels carry packets both ways at once.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            full_duplex: true,
+            ..Capabilities::new(MTU)
         }
     }
+}
 
-    fn read_packet_timeout<DELAY: DelayMs<u16>>(
-        &mut self,
-        timeout_ms: i32,
-        delay: &mut DELAY,
-    ) -> Result<Option<RadioBuffer>, Self::Error> {
-        let mut count = 0;
+/// The channels are unbounded, so `transmit` completes straight away, and a `receive` future dropped
+/// before completing leaves the packet queued for the next one.
+impl<const MTU: usize> AsyncEmbeddedRadio<MTU> for MockLora<MTU> {
+    type Error = <Self as EmbeddedRadio<MTU>>::Error;
 
-        let packet = loop {
-            let packet = self.read_packet()?;
+    async fn transmit(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        let frame = self.frame(&[payload])?;
+        for tx in self.tx.iter() {
+            tx.send(frame.clone())
+                .await
+                .map_err(LoraError::Transmitter)?;
+        }
 
-            if packet.is_some() {
-                break packet;
-            }
+        Ok(())
+    }
 
-            if count >= timeout_ms {
-                break None;
+    async fn receive(&mut self) -> Result<RadioBuffer<MTU>, Self::Error> {
+        loop {
+            let frame = self.rx.recv().await.map_err(LoraError::Receiver)?;
+            if frame.frequency_hz == self.frequency_hz {
+                return Ok(frame.payload);
             }
+        }
+    }
+}
 
-            count += 1;
-            delay.delay_ms(1);
-        };
+/// Accepts the settings of an SX1276, with the same types and ranges as `LoRa`, so code tested against the
+/// mock also works on the real radio.
+impl<const MTU: usize> ConfigurableRadio<MTU> for MockLora<MTU> {
+    type DataRate = LoRaDataRate;
+    /// Bandwidth in Hz.
+    type Bandwidth = u32;
 
-        Ok(packet)
+    fn frequency_range(&self) -> RangeInclusive<u32> {
+        137_000_000..=1_020_000_000
+    }
+
+    fn set_frequency_hz(&mut self, frequency: u32) -> Result<(), Self::Error> {
+        if !self.frequency_range().contains(&frequency) {
+            return Err(LoraError::Unsupported);
+        }
+        self.frequency_hz = frequency;
+        Ok(())
+    }
+
+    /// Depends on the power amplifier selected with `set_pa_select`.
+    fn tx_power_range(&self) -> RangeInclusive<i8> {
+        self.pa_select.tx_power_range()
+    }
+
+    fn set_tx_power_dbm(&mut self, power: i8) -> Result<i8, Self::Error> {
+        if !self.tx_power_range().contains(&power) {
+            return Err(LoraError::Unsupported);
+        }
+        self.tx_power_dbm = power;
+        Ok(power)
+    }
+
+    fn supports_data_rate(&self, rate: &LoRaDataRate) -> bool {
+        rate.is_supported()
+    }
+
+    fn set_data_rate(&mut self, rate: LoRaDataRate) -> Result<(), Self::Error> {
+        if !self.supports_data_rate(&rate) {
+            return Err(LoraError::Unsupported);
+        }
+        self.data_rate = rate;
+        Ok(())
+    }
+
+    fn supports_bandwidth(&self, bandwidth: &u32) -> bool {
+        LORA_BANDWIDTHS.contains(bandwidth)
+    }
+
+    fn set_bandwidth(&mut self, bandwidth: u32) -> Result<(), Self::Error> {
+        if !self.supports_bandwidth(&bandwidth) {
+            return Err(LoraError::Unsupported);
+        }
+        self.bandwidth = bandwidth;
+        Ok(())
     }
 }
 
@@ -112,6 +309,7 @@ impl EmbeddedRadio for MockLora {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use embedded_hal::blocking::delay::DelayMs;
 
     #[test]
     fn no_transmit_self() {
@@ -150,4 +348,195 @@ mod tests {
 
         assert_eq!(received_payload, Some(payload));
     }
+
+    #[test]
+    fn transmit_vectored_concatenates() {
+        let mut loras = MockLora::new(2);
+        let mut lora_1 = loras.pop().unwrap();
+        let mut lora_2 = loras.pop().unwrap();
+
+        lora_1.transmit_vectored(&[&[1], &[2, 3], &[4]]).unwrap();
+        assert_eq!(&lora_2.read_packet().unwrap().unwrap()[..], &[1, 2, 3, 4]);
+
+        assert!(matches!(
+            lora_1.transmit_vectored(&[&[0; 200], &[0; 56]]),
+            Err(LoraError::PayloadTooLarge(256))
+        ));
+        assert_eq!(lora_2.read_packet().unwrap(), None);
+
+        // the default implementation, gathering the slices
+        let mut erased = crate::ErasedRadio::new(lora_1);
+        erased.transmit_vectored(&[&[5], &[6, 7]]).unwrap();
+        assert_eq!(&lora_2.read_packet().unwrap().unwrap()[..], &[5, 6, 7]);
+        assert_eq!(
+            erased.transmit_vectored(&[&[0; 200], &[0; 56]]),
+            Err(RadioErrorKind::InvalidPayload)
+        );
+        assert_eq!(lora_2.read_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn packets_carry_send_time() {
+        struct Ticks(std::sync::atomic::AtomicU64);
+
+        impl Clock for Ticks {
+            fn now_us(&self) -> u64 {
+                self.0.fetch_add(100, std::sync::atomic::Ordering::Relaxed)
+            }
+        }
+
+        let clock = Arc::new(Ticks(std::sync::atomic::AtomicU64::new(0)));
+        let mut loras = MockLora::with_clock(2, clock.clone());
+        let mut lora_1 = loras.pop().unwrap();
+        let mut lora_2 = loras.pop().unwrap();
+
+        lora_1.transmit_payload(&[1]).unwrap();
+        lora_1.transmit_payload(&[2]).unwrap();
+        let packet = lora_2.read_packet_timestamped(&*clock).unwrap().unwrap();
+        assert_eq!((&packet.payload[..], packet.rx_done_us), (&[1][..], 0));
+        let packet = lora_2.read_packet_timestamped(&*clock).unwrap().unwrap();
+        assert_eq!((&packet.payload[..], packet.rx_done_us), (&[2][..], 100));
+    }
+
+    #[test]
+    fn only_radios_on_the_same_channel_receive() {
+        let mut loras = MockLora::new(2);
+        let mut lora_1 = loras.pop().unwrap();
+        let mut lora_2 = loras.pop().unwrap();
+
+        assert!(matches!(
+            lora_1.set_frequency_hz(2_400_000_000),
+            Err(LoraError::Unsupported)
+        ));
+        lora_1.set_frequency_hz(868_100_000).unwrap();
+        lora_1.transmit_payload(&[1]).unwrap();
+        assert_eq!(lora_2.read_packet().unwrap(), None);
+
+        lora_2.set_frequency_hz(868_100_000).unwrap();
+        lora_1.transmit_payload(&[2]).unwrap();
+        assert_eq!(&lora_2.read_packet().unwrap().unwrap()[..], &[2]);
+    }
+
+    #[test]
+    fn settings_match_the_sx127x() {
+        let mut lora = MockLora::new(1).pop().unwrap();
+
+        assert!(lora.set_bandwidth(100_000).is_err());
+        lora.set_bandwidth(62_500).unwrap();
+        let rate = LoRaDataRate {
+            spreading_factor: 13,
+            coding_rate: 5,
+        };
+        assert!(lora.set_data_rate(rate).is_err());
+        lora.set_data_rate(LoRaDataRate {
+            spreading_factor: 12,
+            ..rate
+        })
+        .unwrap();
+        assert_eq!(lora.data_rate().spreading_factor, 12);
+
+        assert!(lora.set_tx_power_dbm(0).is_err());
+        lora.set_pa_select(PaSelect::Rfo);
+        assert_eq!(lora.set_tx_power_dbm(0).unwrap(), 0);
+        assert!(lora.set_tx_power_dbm(20).is_err());
+    }
+
+    #[test]
+    fn async_transmit_and_receive() {
+        let mut loras = MockLora::new(2);
+        let mut lora_1 = loras.pop().unwrap();
+        let mut lora_2 = loras.pop().unwrap();
+
+        pollster::block_on(async {
+            AsyncEmbeddedRadio::transmit(&mut lora_1, &[1, 2])
+                .await
+                .unwrap();
+            let packet = AsyncEmbeddedRadio::receive(&mut lora_2).await.unwrap();
+            assert_eq!(&packet[..], &[1, 2]);
+        });
+        // both sides of the mock share the channels
+        lora_1.transmit_payload(&[3]).unwrap();
+        let packet = pollster::block_on(AsyncEmbeddedRadio::receive(&mut lora_2)).unwrap();
+        assert_eq!(&packet[..], &[3]);
+    }
+
+    #[test]
+    fn radios_as_trait_objects() {
+        use crate::ErasedRadio;
+
+        struct NoDelay;
+
+        impl DelayMs<u16> for NoDelay {
+            fn delay_ms(&mut self, _ms: u16) {}
+        }
+
+        let mut radios: Vec<Box<dyn EmbeddedRadio<Error = RadioErrorKind>>> = MockLora::new(2)
+            .into_iter()
+            .map(|radio| Box::new(ErasedRadio::new(radio)) as Box<_>)
+            .collect();
+
+        radios[0].transmit_payload(&[1]).unwrap();
+        let packet = radios[1].read_packet_timeout(10, &mut NoDelay).unwrap();
+        assert_eq!(&packet.unwrap()[..], &[1]);
+        assert_eq!(
+            radios[0].transmit_payload(&[0; 256]),
+            Err(RadioErrorKind::InvalidPayload)
+        );
+    }
+
+    #[test]
+    fn split_halves_run_on_separate_threads() {
+        let mut loras = MockLora::new(2);
+        let mut remote = loras.pop().unwrap();
+        let shared = crate::SharedRadio::new(loras.pop().unwrap());
+        let (sender, receiver) = shared.split();
+
+        struct SleepDelay;
+
+        impl DelayMs<u16> for SleepDelay {
+            fn delay_ms(&mut self, ms: u16) {
+                std::thread::sleep(std::time::Duration::from_millis(ms.into()));
+            }
+        }
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..3u8 {
+                    sender.send(&[i], &mut SleepDelay).unwrap();
+                }
+            });
+            let received = scope.spawn(|| {
+                let mut received = std::vec::Vec::new();
+                for _ in 0..3 {
+                    received.push(receiver.recv(&mut SleepDelay).unwrap()[0]);
+                }
+                received
+            });
+
+            let mut echoed = 0;
+            while echoed < 3 {
+                if let Some(packet) = remote.read_packet().unwrap() {
+                    remote.transmit_payload(&[packet[0] + 10]).unwrap();
+                    echoed += 1;
+                }
+            }
+            assert_eq!(received.join().unwrap(), [10, 11, 12]);
+        });
+    }
+
+    #[test]
+    fn mtu_limits_payloads() {
+        let mut loras = MockLora::<4>::build(2, None);
+        let mut lora_1 = loras.pop().unwrap();
+        let mut lora_2 = loras.pop().unwrap();
+        assert_eq!(lora_1.capabilities().mtu, 4);
+
+        assert!(matches!(
+            lora_1.transmit_payload(&[0; 5]),
+            Err(LoraError::PayloadTooLarge(5))
+        ));
+        lora_1.transmit_payload(&[1, 2, 3, 4]).unwrap();
+        let packet: heapless::Vec<u8, 4> = lora_2.read_packet().unwrap().unwrap();
+        assert_eq!(&packet[..], &[1, 2, 3, 4]);
+    }
 }
@@ -1,28 +1,307 @@
+use core::fmt::Debug;
+use core::ops::RangeInclusive;
 use embedded_hal::blocking::delay::DelayMs;
 use heapless::Vec;
 
 /// embedded_radio traits, to provide implementations of various radio drivers compatible with embedded_hal.
 /// This mirrors the design of the std::sync::mpsc APIs, and can create mock implementations to support unit testing.
-/// This trait does not cover configuration, because of lack of knowledge on the designer's part. PRs welcome if
-/// a device agnostic way can be found to do that.
-pub trait EmbeddedRadio {
-    type Error;
+/// Configuration is covered by `ConfigurableRadio`.
+///
+/// `MTU` is the largest payload the radio carries, 255 bytes for LoRa packets. Received packets are returned in
+/// buffers of that capacity, so generic code can size its own buffers from it.
+///
+/// The trait is object safe, so radios of different kinds can be kept together as
+/// `Box<dyn EmbeddedRadio<Error = RadioErrorKind>>` by wrapping them in `ErasedRadio`.
+pub trait EmbeddedRadio<const MTU: usize = 255> {
+    /// Error of the driver, which also reports payloads longer than `MTU` bytes.
+    type Error: From<OversizedPayload>;
 
-    /// Attempts to send a value on this channel. Unsuccessful sends can result from hardware errors.
+    /// Attempts to send a value on this channel. Unsuccessful sends can result from hardware errors, or from payloads
+    /// longer than `MTU` bytes.
     fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error>;
+    /// Attempts to send the concatenation of `payloads` as one packet. Sends whose combined length exceeds `MTU`
+    /// bytes are rejected. By default the slices are gathered in a buffer and sent with `transmit_payload`;
+    /// drivers which can stream them to the radio one after the other override this.
+    fn transmit_vectored(&mut self, payloads: &[&[u8]]) -> Result<(), Self::Error> {
+        let mut packet: Vec<u8, MTU> = Vec::new();
+        for payload in payloads {
+            packet.extend_from_slice(payload).map_err(|_| {
+                OversizedPayload(payloads.iter().map(|payload| payload.len()).sum())
+            })?;
+        }
+        self.transmit_payload(&packet)
+    }
     /// Blocks until the payload has been sent from the transmitter. Unsuccessful sends can result from hardware errors.
     fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), Self::Error>;
     /// Will return a boolean value of whether or not the radio is still transmitting.
     fn transmitting(&mut self) -> Result<bool, Self::Error>;
+    /// Returns true if a packet has arrived which has not been read yet, without reading it or changing the
+    /// radio's mode. Half duplex radios which drop such a packet when they start transmitting override this; by
+    /// default it returns false, for radios which keep received packets while they transmit.
+    fn packet_pending(&mut self) -> Result<bool, Self::Error> {
+        Ok(false)
+    }
 
     /// Attempts to read a value on this channel. Unsuccessful reads result from a packet not being present.
-    /// Successful reads would be one where up to 255 bytes of data are received.
-    fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, Self::Error>;
+    /// Successful reads would be one where up to `MTU` bytes of data are received.
+    fn read_packet(&mut self) -> Result<Option<Vec<u8, MTU>>, Self::Error>;
+    /// Attempts to read a value on this channel directly into `buffer`, returning the length of the received packet.
+    /// If the packet is longer than `buffer`, only `buffer.len()` bytes are written and the rest of the packet is
+    /// discarded, which callers detect by the returned length exceeding the buffer.
+    fn read_packet_into(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        Ok(self.read_packet()?.map(|packet| {
+            let len = packet.len().min(buffer.len());
+            buffer[..len].copy_from_slice(&packet[..len]);
+            packet.len()
+        }))
+    }
     /// Attempts to read a value on this channel. Unsuccessful reads can result from a hardware failure or the specified timeout passing.
-    /// Successful reads would be ones where up to 255 bytes of data are received.
-    fn read_packet_timeout<DELAY: DelayMs<u16>>(
+    /// Successful reads would be ones where up to `MTU` bytes of data are received. Polls `read_packet` every millisecond.
+    fn read_packet_timeout(
         &mut self,
         timeout_ms: i32,
-        delay: &mut DELAY,
-    ) -> Result<Option<Vec<u8, 255>>, Self::Error>;
+        delay: &mut dyn DelayMs<u16>,
+    ) -> Result<Option<Vec<u8, MTU>>, Self::Error> {
+        let mut count = 0;
+
+        let packet = loop {
+            let packet = self.read_packet()?;
+
+            if packet.is_some() {
+                break packet;
+            }
+
+            if count >= timeout_ms {
+                break None;
+            }
+
+            count += 1;
+            delay.delay_ms(1);
+        };
+
+        Ok(packet)
+    }
+
+    /// Describes what the radio can do. Radios which do not override this report only their `MTU`.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::new(MTU)
+    }
+}
+
+/// Features of a radio, returned by `EmbeddedRadio::capabilities`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// Largest payload, in bytes.
+    pub mtu: usize,
+    /// The radio receives while it transmits.
+    pub full_duplex: bool,
+    /// The signal strength of received packets can be read.
+    pub rssi: bool,
+    /// The signal to noise ratio of received packets can be read.
+    pub snr: bool,
+    /// The radio can detect channel activity without receiving a packet.
+    pub channel_activity_detection: bool,
+}
+
+impl Capabilities {
+    /// Returns the capabilities of a half duplex radio with nothing beyond sending and receiving packets of up to
+    /// `mtu` bytes.
+    pub const fn new(mtu: usize) -> Self {
+        Capabilities {
+            mtu,
+            full_duplex: false,
+            rssi: false,
+            snr: false,
+            channel_activity_detection: false,
+        }
+    }
+}
+
+/// Payload whose length, in bytes, exceeds the `MTU` of the radio. Returned through the driver's error by the
+/// default `EmbeddedRadio::transmit_vectored`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OversizedPayload(pub usize);
+
+/// Kind of failure reported by a radio, independent of the driver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RadioErrorKind {
+    /// The bus or a pin connecting the radio failed.
+    Bus,
+    /// The radio did not respond as expected, e.g. it is missing or an unknown chip.
+    Device,
+    /// The radio is still busy transmitting.
+    Busy,
+    /// The payload does not fit the packet format.
+    InvalidPayload,
+    /// The setting is outside of what the radio supports.
+    InvalidSetting,
+    /// The radio did not complete in time.
+    Timeout,
+    /// The other end of a simulated radio link is gone.
+    Disconnected,
+    /// Any other failure.
+    Other,
+}
+
+/// Error of a radio driver, which can be reduced to a `RadioErrorKind` by generic code.
+pub trait RadioError: Debug {
+    /// Returns the kind of failure.
+    fn kind(&self) -> RadioErrorKind;
+}
+
+impl RadioError for RadioErrorKind {
+    fn kind(&self) -> RadioErrorKind {
+        *self
+    }
+}
+
+impl From<OversizedPayload> for RadioErrorKind {
+    fn from(_: OversizedPayload) -> Self {
+        RadioErrorKind::InvalidPayload
+    }
+}
+
+/// Wraps a radio so its errors are reduced to their `RadioErrorKind`, giving radios of different kinds the same
+/// `EmbeddedRadio` type to be used as trait objects.
+pub struct ErasedRadio<R>(R);
+
+impl<R> ErasedRadio<R> {
+    pub fn new(radio: R) -> Self {
+        ErasedRadio(radio)
+    }
+
+    /// Returns the wrapped radio.
+    pub fn into_inner(self) -> R {
+        self.0
+    }
+}
+
+impl<R, const MTU: usize> EmbeddedRadio<MTU> for ErasedRadio<R>
+where
+    R: EmbeddedRadio<MTU>,
+    R::Error: RadioError,
+{
+    type Error = RadioErrorKind;
+
+    fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.0.transmit_payload(payload).map_err(|e| e.kind())
+    }
+
+    fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.0.transmit_payload_busy(payload).map_err(|e| e.kind())
+    }
+
+    fn transmitting(&mut self) -> Result<bool, Self::Error> {
+        self.0.transmitting().map_err(|e| e.kind())
+    }
+
+    fn packet_pending(&mut self) -> Result<bool, Self::Error> {
+        self.0.packet_pending().map_err(|e| e.kind())
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Vec<u8, MTU>>, Self::Error> {
+        self.0.read_packet().map_err(|e| e.kind())
+    }
+
+    fn read_packet_into(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        self.0.read_packet_into(buffer).map_err(|e| e.kind())
+    }
+
+    fn read_packet_timeout(
+        &mut self,
+        timeout_ms: i32,
+        delay: &mut dyn DelayMs<u16>,
+    ) -> Result<Option<Vec<u8, MTU>>, Self::Error> {
+        self.0
+            .read_packet_timeout(timeout_ms, delay)
+            .map_err(|e| e.kind())
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.0.capabilities()
+    }
+}
+
+/// Device agnostic configuration of a radio, so applications can switch channel, power and data rate without
+/// knowing the chip. Modulation parameters which differ between radios are associated types, and the supported
+/// values can be queried before applying them. Settings outside of what the radio supports are rejected.
+pub trait ConfigurableRadio<const MTU: usize = 255>: EmbeddedRadio<MTU> {
+    /// Parameters setting the data rate, e.g. spreading factor and coding rate for LoRa.
+    type DataRate;
+    /// Channel bandwidth setting.
+    type Bandwidth;
+
+    /// Returns the supported carrier frequencies, in Hz.
+    fn frequency_range(&self) -> RangeInclusive<u32>;
+    /// Tunes the radio to a carrier frequency in Hz.
+    fn set_frequency_hz(&mut self, frequency: u32) -> Result<(), Self::Error>;
+
+    /// Returns the supported output powers, in dBm.
+    fn tx_power_range(&self) -> RangeInclusive<i8>;
+    /// Sets the output power in dBm, returning the power actually programmed.
+    fn set_tx_power_dbm(&mut self, power: i8) -> Result<i8, Self::Error>;
+
+    /// Returns true if the radio can use the data rate.
+    fn supports_data_rate(&self, rate: &Self::DataRate) -> bool;
+    /// Sets the data rate.
+    fn set_data_rate(&mut self, rate: Self::DataRate) -> Result<(), Self::Error>;
+
+    /// Returns true if the radio can use the channel bandwidth.
+    fn supports_bandwidth(&self, bandwidth: &Self::Bandwidth) -> bool;
+    /// Sets the channel bandwidth.
+    fn set_bandwidth(&mut self, bandwidth: Self::Bandwidth) -> Result<(), Self::Error>;
+}
+
+/// Channel bandwidths of LoRa modems, in Hz.
+pub(crate) const LORA_BANDWIDTHS: [u32; 10] = [
+    7_800, 10_400, 15_600, 20_800, 31_250, 41_700, 62_500, 125_000, 250_000, 500_000,
+];
+
+/// Data rate of a LoRa modem, as used by `ConfigurableRadio`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoRaDataRate {
+    /// Spreading factor, between `6` and `12`. Spreading factor 6 needs `HeaderMode::Implicit`.
+    pub spreading_factor: u8,
+    /// Coding rate denominator, between `5` and `8`, for `4/5` to `4/8`.
+    pub coding_rate: u8,
+}
+
+impl LoRaDataRate {
+    pub(crate) fn is_supported(&self) -> bool {
+        (6..=12).contains(&self.spreading_factor) && (5..=8).contains(&self.coding_rate)
+    }
+}
+
+/// The power amplifier, and output pin, used to transmit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaSelect {
+    /// The RFO pin, from -4 to +15 dBm.
+    Rfo = 0x00,
+    /// The PA_BOOST pin, from +2 to +20 dBm.
+    PaBoost = 0x80,
+}
+
+impl PaSelect {
+    /// Returns the output power range of the power amplifier, in dBm.
+    pub(crate) fn tx_power_range(self) -> RangeInclusive<i8> {
+        match self {
+            PaSelect::Rfo => -4..=15,
+            PaSelect::PaBoost => 2..=20,
+        }
+    }
+}
+
+/// Asynchronous counterpart of `EmbeddedRadio`, so firmware running on an executor (embassy, tokio, ...) waits
+/// for the radio instead of polling it. The futures are cancellation safe: dropping a `receive` future loses no
+/// packet, which is returned by the next call, and dropping a `transmit` future once the packet has been handed
+/// to the radio does not abort it.
+#[allow(async_fn_in_trait)]
+pub trait AsyncEmbeddedRadio<const MTU: usize = 255> {
+    type Error;
+
+    /// Sends up to `MTU` bytes, completing once the packet has left the transmitter.
+    async fn transmit(&mut self, payload: &[u8]) -> Result<(), Self::Error>;
+    /// Waits for the next packet and returns it.
+    async fn receive(&mut self) -> Result<Vec<u8, MTU>, Self::Error>;
 }
@@ -0,0 +1,569 @@
+use crate::EmbeddedRadio;
+use crossbeam::channel::{self, Receiver, Sender, TrySendError};
+use heapless::Vec;
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A packet received by a `RadioService`, with what is known about its reception.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedPacket<const MTU: usize = 255> {
+    pub payload: Vec<u8, MTU>,
+    /// Wall clock time at which the service picked the packet up from the radio.
+    pub received_at: SystemTime,
+    /// Signal strength in dBm, if the service was given `ServiceOptions::signal_quality`.
+    pub rssi: Option<i32>,
+    /// Signal to noise ratio in dB, if the service was given `ServiceOptions::signal_quality`.
+    pub snr: Option<f64>,
+}
+
+/// Settings of a `RadioService`.
+pub struct ServiceOptions<R> {
+    /// How long the service thread sleeps when the radio has nothing to do.
+    pub poll_interval: Duration,
+    /// Number of transmissions which can be queued before `ServiceHandle::transmit` blocks.
+    pub tx_queue_len: usize,
+    /// Number of received packets kept for the application. Packets arriving when it is full are dropped.
+    pub rx_queue_len: usize,
+    /// Number of radio errors kept for the application, at least one. When it is full, the oldest error is dropped
+    /// to make room for the new one.
+    pub error_queue_len: usize,
+    /// Reads the RSSI and SNR of the packet just received, e.g. with `LoRa::get_packet_rssi`.
+    pub signal_quality: Option<SignalQuality<R>>,
+    /// Longest time the radio may report transmitting, e.g. the time on air of the longest packet given by
+    /// `LoRa::time_on_air_us` plus a margin. The transmission is then abandoned with `abort_transmission`.
+    pub tx_timeout: Duration,
+    /// Puts a radio whose transmission timed out back in standby, e.g. with `LoRa::set_mode`.
+    pub abort_transmission: Option<fn(&mut R)>,
+}
+
+impl<R> Default for ServiceOptions<R> {
+    fn default() -> Self {
+        ServiceOptions {
+            poll_interval: Duration::from_millis(1),
+            tx_queue_len: 16,
+            rx_queue_len: 64,
+            error_queue_len: 16,
+            signal_quality: None,
+            tx_timeout: Duration::from_secs(10),
+            abort_transmission: None,
+        }
+    }
+}
+
+/// Error returned by a `ServiceHandle`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ServiceError {
+    /// The payload is longer than the radio `MTU`.
+    PayloadTooLarge(usize),
+    /// The transmit queue is full.
+    QueueFull,
+    /// The service thread has stopped.
+    Stopped,
+}
+
+type SignalQuality<R> = fn(&mut R) -> (Option<i32>, Option<f64>);
+
+type Configure<R> = Box<dyn FnOnce(&mut R) + Send>;
+
+enum Command<R> {
+    Configure(Configure<R>),
+    Shutdown,
+}
+
+/// Runs a radio on its own thread, so applications on a Linux host send and receive through channels instead of
+/// sharing the SPI device.
+///
+/// The thread polls the radio for packets, which are delivered on `packets()`, and sends the payloads queued with
+/// `ServiceHandle::transmit` one after the other. Configuration changes submitted with `ServiceHandle::configure`
+/// are applied between transmissions. Radio errors do not stop the service; they are reported on `errors()`.
+///
+/// ```ignore
+/// let service = RadioService::spawn(lora, ServiceOptions::default())?;
+/// let handle = service.handle();
+/// handle.configure(|lora| lora.set_frequency_hz(868_100_000))??;
+/// handle.transmit(b"hello")?;
+/// for packet in service.packets() {
+///     println!("{:?} at {:?}", packet.payload, packet.rssi);
+/// }
+/// ```
+pub struct RadioService<R, const MTU: usize = 255>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    handle: ServiceHandle<R, MTU>,
+    packets: Receiver<ReceivedPacket<MTU>>,
+    errors: Receiver<R::Error>,
+    thread: Option<JoinHandle<R>>,
+}
+
+impl<R, const MTU: usize> RadioService<R, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+    R::Error: Send + 'static,
+{
+    /// Moves the radio to a new thread and starts receiving.
+    pub fn spawn(radio: R, options: ServiceOptions<R>) -> io::Result<Self> {
+        let (commands, command_rx) = channel::unbounded();
+        let (transmissions, transmission_rx) = channel::bounded(options.tx_queue_len);
+        let (packet_tx, packets) = channel::bounded(options.rx_queue_len);
+        let (error_tx, errors) = channel::bounded(options.error_queue_len.max(1));
+
+        let worker = Worker {
+            radio,
+            options,
+            transmitting_since: None,
+            deferred: VecDeque::new(),
+            commands: command_rx,
+            transmissions: transmission_rx,
+            packets: packet_tx,
+            errors: error_tx,
+            oldest_errors: errors.clone(),
+        };
+        let thread = thread::Builder::new()
+            .name("radio-service".into())
+            .spawn(move || worker.run())?;
+
+        Ok(RadioService {
+            handle: ServiceHandle {
+                commands,
+                transmissions,
+            },
+            packets,
+            errors,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns a handle submitting transmissions and configuration changes, which can be cloned and sent to
+    /// other threads.
+    pub fn handle(&self) -> ServiceHandle<R, MTU> {
+        self.handle.clone()
+    }
+
+    /// Returns the channel of received packets.
+    pub fn packets(&self) -> &Receiver<ReceivedPacket<MTU>> {
+        &self.packets
+    }
+
+    /// Returns the channel of errors reported by the radio.
+    pub fn errors(&self) -> &Receiver<R::Error> {
+        &self.errors
+    }
+
+    /// Stops the service and returns the radio, without waiting for the current transmission to be over. Queued
+    /// transmissions and configuration changes are discarded.
+    pub fn shutdown(mut self) -> thread::Result<R> {
+        let _ = self.handle.commands.send(Command::Shutdown);
+        self.thread
+            .take()
+            .expect("service thread already stopped")
+            .join()
+    }
+}
+
+impl<R, const MTU: usize> Drop for RadioService<R, MTU>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = self.handle.commands.send(Command::Shutdown);
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Submits work to a `RadioService`.
+pub struct ServiceHandle<R, const MTU: usize = 255> {
+    commands: Sender<Command<R>>,
+    transmissions: Sender<Vec<u8, MTU>>,
+}
+
+impl<R, const MTU: usize> Clone for ServiceHandle<R, MTU> {
+    fn clone(&self) -> Self {
+        ServiceHandle {
+            commands: self.commands.clone(),
+            transmissions: self.transmissions.clone(),
+        }
+    }
+}
+
+impl<R, const MTU: usize> ServiceHandle<R, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+{
+    /// Queues the payload for transmission, waiting for room in the queue if it is full.
+    pub fn transmit(&self, payload: &[u8]) -> Result<(), ServiceError> {
+        self.transmissions
+            .send(Self::packet(payload)?)
+            .map_err(|_| ServiceError::Stopped)
+    }
+
+    /// Queues the payload for transmission, or returns `QueueFull` if the queue is full.
+    pub fn try_transmit(&self, payload: &[u8]) -> Result<(), ServiceError> {
+        self.transmissions
+            .try_send(Self::packet(payload)?)
+            .map_err(|e| match e {
+                TrySendError::Full(_) => ServiceError::QueueFull,
+                TrySendError::Disconnected(_) => ServiceError::Stopped,
+            })
+    }
+
+    /// Runs `f` on the service thread once the current transmission is over, and returns its result.
+    pub fn configure<T, F>(&self, f: F) -> Result<T, ServiceError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut R) -> T + Send + 'static,
+    {
+        let (reply, result) = channel::bounded(1);
+        self.commands
+            .send(Command::Configure(Box::new(move |radio: &mut R| {
+                let _ = reply.send(f(radio));
+            })))
+            .map_err(|_| ServiceError::Stopped)?;
+        result.recv().map_err(|_| ServiceError::Stopped)
+    }
+
+    fn packet(payload: &[u8]) -> Result<Vec<u8, MTU>, ServiceError> {
+        Vec::from_slice(payload).map_err(|_| ServiceError::PayloadTooLarge(payload.len()))
+    }
+}
+
+/// State of the service thread.
+struct Worker<R, const MTU: usize>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    radio: R,
+    options: ServiceOptions<R>,
+    /// When the radio was first seen transmitting, for `ServiceOptions::tx_timeout`.
+    transmitting_since: Option<Instant>,
+    /// Configuration changes received during a transmission, applied once it is over.
+    deferred: VecDeque<Configure<R>>,
+    commands: Receiver<Command<R>>,
+    transmissions: Receiver<Vec<u8, MTU>>,
+    packets: Sender<ReceivedPacket<MTU>>,
+    errors: Sender<R::Error>,
+    /// Receiving end of `errors`, to drop the oldest error when it is full.
+    oldest_errors: Receiver<R::Error>,
+}
+
+impl<R, const MTU: usize> Worker<R, MTU>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    fn run(mut self) -> R {
+        loop {
+            // polling for packets while transmitting would abort a half duplex radio
+            let wait = match self.radio.transmitting() {
+                Ok(true) => {
+                    let since = *self.transmitting_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() < self.options.tx_timeout {
+                        true
+                    } else {
+                        self.abort_transmission();
+                        false
+                    }
+                }
+                Ok(false) => {
+                    self.transmitting_since = None;
+                    false
+                }
+                Err(e) => {
+                    self.report(e);
+                    true
+                }
+            };
+            if wait {
+                if self.wait_for_radio() {
+                    return self.radio;
+                }
+                continue;
+            }
+
+            while let Some(f) = self.deferred.pop_front() {
+                f(&mut self.radio);
+            }
+            while let Ok(command) = self.commands.try_recv() {
+                match command {
+                    Command::Configure(f) => f(&mut self.radio),
+                    Command::Shutdown => return self.radio,
+                }
+            }
+
+            let received = self.receive();
+            let transmitted = self.transmit();
+            if received || transmitted {
+                continue;
+            }
+
+            // idle: wake up early for new work
+            crossbeam::select! {
+                recv(self.commands) -> command => match command {
+                    Ok(Command::Configure(f)) => f(&mut self.radio),
+                    Ok(Command::Shutdown) | Err(_) => return self.radio,
+                },
+                recv(self.transmissions) -> payload => {
+                    if let Ok(payload) = payload {
+                        self.send(&payload);
+                    }
+                },
+                default(self.options.poll_interval) => {},
+            }
+        }
+    }
+
+    /// Waits for the poll interval while the radio is busy, keeping configuration changes for later. Returns true
+    /// if the service is shutting down.
+    fn wait_for_radio(&mut self) -> bool {
+        crossbeam::select! {
+            recv(self.commands) -> command => match command {
+                Ok(Command::Configure(f)) => self.deferred.push_back(f),
+                Ok(Command::Shutdown) | Err(_) => return true,
+            },
+            default(self.options.poll_interval) => {},
+        }
+        false
+    }
+
+    /// Gives up on a transmission which took longer than `tx_timeout`.
+    fn abort_transmission(&mut self) {
+        if let Some(abort_transmission) = self.options.abort_transmission {
+            abort_transmission(&mut self.radio);
+        }
+        self.transmitting_since = None;
+    }
+
+    /// Forwards a received packet, returning true if there was one.
+    fn receive(&mut self) -> bool {
+        match self.radio.read_packet() {
+            Ok(Some(payload)) => {
+                let (rssi, snr) = match self.options.signal_quality {
+                    Some(signal_quality) => signal_quality(&mut self.radio),
+                    None => (None, None),
+                };
+                let _ = self.packets.try_send(ReceivedPacket {
+                    payload,
+                    received_at: SystemTime::now(),
+                    rssi,
+                    snr,
+                });
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                self.report(e);
+                false
+            }
+        }
+    }
+
+    /// Starts the next queued transmission, returning true if there was one.
+    fn transmit(&mut self) -> bool {
+        match self.transmissions.try_recv() {
+            Ok(payload) => {
+                self.send(&payload);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn send(&mut self, payload: &[u8]) {
+        if let Err(e) = self.radio.transmit_payload(payload) {
+            self.report(e);
+        }
+    }
+
+    fn report(&self, mut error: R::Error) {
+        loop {
+            match self.errors.try_send(error) {
+                Err(TrySendError::Full(e)) => {
+                    let _ = self.oldest_errors.try_recv();
+                    error = e;
+                }
+                Ok(()) | Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{ConfigurableRadio, MockLora, OversizedPayload, RadioErrorKind};
+
+    #[test]
+    #[allow(clippy::result_large_err)]
+    fn service_sends_receives_and_configures() {
+        let mut loras = MockLora::new(2);
+        let mut remote = loras.pop().unwrap();
+        let options = ServiceOptions {
+            signal_quality: Some(|_: &mut MockLora| (Some(-42), None)),
+            ..ServiceOptions::default()
+        };
+        let service = RadioService::spawn(loras.pop().unwrap(), options).unwrap();
+        let handle = service.handle();
+
+        handle.transmit(&[1, 2, 3]).unwrap();
+        let packet = loop {
+            if let Some(packet) = remote.read_packet().unwrap() {
+                break packet;
+            }
+        };
+        assert_eq!(&packet[..], &[1, 2, 3]);
+        assert_eq!(
+            handle.transmit(&[0; 256]),
+            Err(ServiceError::PayloadTooLarge(256))
+        );
+
+        remote.transmit_payload(&[4, 5]).unwrap();
+        let received = service
+            .packets()
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(&received.payload[..], &[4, 5]);
+        assert_eq!(received.rssi, Some(-42));
+
+        handle
+            .configure(|radio| radio.set_frequency_hz(868_100_000))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            handle.configure(|radio| radio.frequency_hz()),
+            Ok(868_100_000)
+        );
+        assert!(service.errors().is_empty());
+
+        let radio = service.shutdown().unwrap();
+        assert_eq!(radio.frequency_hz(), 868_100_000);
+        assert_eq!(handle.try_transmit(&[1]), Err(ServiceError::Stopped));
+    }
+
+    /// Radio whose transmissions never end unless aborted.
+    #[derive(Default)]
+    struct StuckRadio {
+        transmitting: bool,
+        aborted: usize,
+    }
+
+    impl EmbeddedRadio for StuckRadio {
+        type Error = RadioErrorKind;
+
+        fn transmit_payload(&mut self, _payload: &[u8]) -> Result<(), RadioErrorKind> {
+            self.transmitting = true;
+            Ok(())
+        }
+
+        fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), RadioErrorKind> {
+            self.transmit_payload(payload)
+        }
+
+        fn transmitting(&mut self) -> Result<bool, RadioErrorKind> {
+            Ok(self.transmitting)
+        }
+
+        fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, RadioErrorKind> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn stuck_transmission_times_out_and_shutdown_does_not_wait() {
+        let options = ServiceOptions {
+            tx_timeout: Duration::from_millis(20),
+            abort_transmission: Some(|radio: &mut StuckRadio| {
+                radio.transmitting = false;
+                radio.aborted += 1;
+            }),
+            ..ServiceOptions::default()
+        };
+        let service = RadioService::spawn(StuckRadio::default(), options).unwrap();
+        let handle = service.handle();
+        handle.transmit(&[1]).unwrap();
+        while !handle.transmissions.is_empty() {
+            thread::yield_now();
+        }
+        // applied once the transmission is aborted
+        assert_eq!(
+            handle.configure(|radio| (radio.transmitting, radio.aborted)),
+            Ok((false, 1))
+        );
+
+        let options = ServiceOptions {
+            tx_timeout: Duration::from_secs(3600),
+            ..ServiceOptions::default()
+        };
+        let service = RadioService::spawn(StuckRadio::default(), options).unwrap();
+        service.handle().transmit(&[1]).unwrap();
+        while !service.handle().transmissions.is_empty() {
+            thread::yield_now();
+        }
+        let radio = service.shutdown().unwrap();
+        assert!(radio.transmitting);
+    }
+
+    /// Radio failing every poll with the number of the failure.
+    #[derive(Default)]
+    struct FailingRadio {
+        failures: u32,
+    }
+
+    /// Error of a `FailingRadio`, numbering its failures.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Failure(u32);
+
+    impl From<OversizedPayload> for Failure {
+        fn from(_: OversizedPayload) -> Self {
+            Failure(0)
+        }
+    }
+
+    impl EmbeddedRadio for FailingRadio {
+        type Error = Failure;
+
+        fn transmit_payload(&mut self, _payload: &[u8]) -> Result<(), Failure> {
+            Ok(())
+        }
+
+        fn transmit_payload_busy(&mut self, _payload: &[u8]) -> Result<(), Failure> {
+            Ok(())
+        }
+
+        fn transmitting(&mut self) -> Result<bool, Failure> {
+            self.failures += 1;
+            Err(Failure(self.failures))
+        }
+
+        fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, Failure> {
+            Ok(None)
+        }
+    }
+
+    #[test]
+    fn errors_keep_the_newest() {
+        let options = ServiceOptions {
+            rx_queue_len: 1,
+            error_queue_len: 3,
+            ..ServiceOptions::default()
+        };
+        let service = RadioService::spawn(FailingRadio::default(), options).unwrap();
+        while service.errors().len() < 3 {
+            thread::yield_now();
+        }
+        thread::sleep(Duration::from_millis(20));
+        let failures = service.errors.clone();
+        let radio = service.shutdown().unwrap();
+
+        let errors: std::vec::Vec<Failure> = failures.try_iter().collect();
+        let last = radio.failures;
+        assert!(last > 3);
+        assert_eq!(
+            errors,
+            [Failure(last - 2), Failure(last - 1), Failure(last)]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use core::cell::{Cell, UnsafeCell};
+use core::ops::{Deref, DerefMut};
+
+use critical_section::Mutex;
+use embedded_hal::blocking::delay::DelayMs;
+use heapless::Vec;
+
+use crate::radio_traits::EmbeddedRadio;
+
+/// A radio shared between a `RadioSender` and a `RadioReceiver`, so that transmitting and receiving can be done
+/// from different tasks, threads or interrupt handlers, like the two halves of a `std::sync::mpsc` channel.
+///
+/// A half claims the radio in a short critical section and releases it once done, so the SPI transfers run with
+/// interrupts enabled. A half finding the radio claimed by the other one does not wait: `try_send` returns `Busy`
+/// and `try_recv` returns `None`. As the radio is half duplex, the halves also take turns on air: the receiver
+/// does not touch the radio while a packet is being sent, and the sender does not start a transmission while a
+/// received packet waits for the receiver, as switching to Tx would drop it.
+///
+/// `send`, `recv` and `with` wait for the radio, backing off for a millisecond between attempts, and must not be
+/// called from an interrupt handler which may have preempted the other half while it holds the radio. Their `try_`
+/// counterparts give up straight away instead.
+///
+/// ```ignore
+/// static RADIO: SharedRadio<MyLoRa> = ...;
+/// let (tx, rx) = RADIO.split();
+/// ```
+pub struct SharedRadio<R, const MTU: usize = 255> {
+    /// Whether the radio is in use by a half or by `with`.
+    claimed: Mutex<Cell<bool>>,
+    radio: UnsafeCell<R>,
+}
+
+// the radio is only reached through a `Claim`, of which there is at most one at a time
+unsafe impl<R: Send, const MTU: usize> Sync for SharedRadio<R, MTU> {}
+
+/// Exclusive access to the radio of a `SharedRadio`, released when dropped.
+struct Claim<'a, R, const MTU: usize> {
+    shared: &'a SharedRadio<R, MTU>,
+}
+
+impl<R, const MTU: usize> Deref for Claim<'_, R, MTU> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        // SAFETY: the claim is exclusive
+        unsafe { &*self.shared.radio.get() }
+    }
+}
+
+impl<R, const MTU: usize> DerefMut for Claim<'_, R, MTU> {
+    fn deref_mut(&mut self) -> &mut R {
+        // SAFETY: the claim is exclusive
+        unsafe { &mut *self.shared.radio.get() }
+    }
+}
+
+impl<R, const MTU: usize> Drop for Claim<'_, R, MTU> {
+    fn drop(&mut self) {
+        critical_section::with(|cs| self.shared.claimed.borrow(cs).set(false));
+    }
+}
+
+/// Error of the split halves of a radio.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SplitError<E> {
+    /// The radio is in use by the other half: a packet is being sent, or a received packet has not been read yet.
+    Busy,
+    /// The radio failed.
+    Radio(E),
+}
+
+impl<R, const MTU: usize> SharedRadio<R, MTU>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    pub const fn new(radio: R) -> Self {
+        SharedRadio {
+            claimed: Mutex::new(Cell::new(false)),
+            radio: UnsafeCell::new(radio),
+        }
+    }
+
+    /// Returns the sending and the receiving half. Both can be used at the same time, from different contexts.
+    pub fn split(&self) -> (RadioSender<'_, R, MTU>, RadioReceiver<'_, R, MTU>) {
+        (RadioSender { shared: self }, RadioReceiver { shared: self })
+    }
+
+    /// Runs `f` with exclusive access to the radio, e.g. to configure it, once the halves are done with it. Retries
+    /// every millisecond while a half is using the radio.
+    pub fn with<T>(&self, delay: &mut impl DelayMs<u16>, f: impl FnOnce(&mut R) -> T) -> T {
+        let mut radio = loop {
+            if let Some(radio) = self.try_claim() {
+                break radio;
+            }
+            delay.delay_ms(1);
+        };
+        f(&mut radio)
+    }
+
+    /// Runs `f` with exclusive access to the radio, or returns `None` if a half is using it.
+    pub fn try_with<T>(&self, f: impl FnOnce(&mut R) -> T) -> Option<T> {
+        self.try_claim().map(|mut radio| f(&mut radio))
+    }
+
+    /// Return ownership of the radio.
+    pub fn into_inner(self) -> R {
+        self.radio.into_inner()
+    }
+
+    /// Claims the radio, unless it is in use.
+    fn try_claim(&self) -> Option<Claim<'_, R, MTU>> {
+        critical_section::with(|cs| {
+            let claimed = self.claimed.borrow(cs);
+            if claimed.get() {
+                None
+            } else {
+                claimed.set(true);
+                Some(Claim { shared: self })
+            }
+        })
+    }
+}
+
+/// Sending half of a `SharedRadio`.
+pub struct RadioSender<'a, R, const MTU: usize = 255> {
+    shared: &'a SharedRadio<R, MTU>,
+}
+
+impl<R, const MTU: usize> RadioSender<'_, R, MTU>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    /// Starts sending the payload, or returns `Busy` if the receiver is using the radio, the radio is still
+    /// sending the previous payload, or it holds a packet the receiver has not read yet.
+    pub fn try_send(&self, payload: &[u8]) -> Result<(), SplitError<R::Error>> {
+        let mut radio = self.shared.try_claim().ok_or(SplitError::Busy)?;
+        // switching to Tx would drop a packet which already arrived
+        if radio.transmitting().map_err(SplitError::Radio)?
+            || radio.packet_pending().map_err(SplitError::Radio)?
+        {
+            return Err(SplitError::Busy);
+        }
+        radio.transmit_payload(payload).map_err(SplitError::Radio)
+    }
+
+    /// Sends the payload, retrying every millisecond for as long as the radio is `Busy`. Each attempt takes a
+    /// critical section and, if the radio is free, a few SPI transfers.
+    ///
+    /// A packet which arrived keeps the radio `Busy` until the receiver reads it, so this never returns if nothing
+    /// polls the receiving half. Use `try_send` to give up instead.
+    pub fn send(&self, payload: &[u8], delay: &mut impl DelayMs<u16>) -> Result<(), R::Error> {
+        loop {
+            match self.try_send(payload) {
+                Ok(()) => return Ok(()),
+                Err(SplitError::Busy) => delay.delay_ms(1),
+                Err(SplitError::Radio(e)) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns true if a packet is being sent, or `Busy` if the receiver is using the radio.
+    pub fn transmitting(&self) -> Result<bool, SplitError<R::Error>> {
+        let mut radio = self.shared.try_claim().ok_or(SplitError::Busy)?;
+        radio.transmitting().map_err(SplitError::Radio)
+    }
+}
+
+/// Receiving half of a `SharedRadio`.
+pub struct RadioReceiver<'a, R, const MTU: usize = 255> {
+    shared: &'a SharedRadio<R, MTU>,
+}
+
+impl<R, const MTU: usize> RadioReceiver<'_, R, MTU>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    /// Returns a packet if one has arrived. Returns `None` without touching the radio while the sender is using
+    /// it or a packet is being sent.
+    pub fn try_recv(&self) -> Result<Option<Vec<u8, MTU>>, R::Error> {
+        let mut radio = match self.shared.try_claim() {
+            Some(radio) => radio,
+            None => return Ok(None),
+        };
+        // polling would put the radio back in receive mode, cutting the transmission off
+        if radio.transmitting()? {
+            return Ok(None);
+        }
+        radio.read_packet()
+    }
+
+    /// Waits for a packet, polling the radio every millisecond. Each poll takes a critical section and, if the
+    /// radio is free, a few SPI transfers.
+    pub fn recv(&self, delay: &mut impl DelayMs<u16>) -> Result<Vec<u8, MTU>, R::Error> {
+        loop {
+            if let Some(packet) = self.try_recv()? {
+                return Ok(packet);
+            }
+            delay.delay_ms(1);
+        }
+    }
+}
@@ -1,59 +1,185 @@
 use bit_field::BitField;
+use core::convert::Infallible;
+use core::fmt::Debug;
+use core::ops::RangeInclusive;
 use heapless::Vec;
 
 use embedded_hal::blocking::delay::DelayMs;
 use embedded_hal::blocking::spi::{Transfer, Write};
-use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
 use embedded_hal::spi::Mode;
 
+#[cfg(feature = "async")]
+mod asynch;
+mod cache;
+pub mod dio;
+mod irq;
+#[cfg(feature = "linux")]
+mod linux;
+mod pins;
 mod register;
+#[cfg(feature = "async")]
+pub use asynch::AsyncLoRa;
+use cache::RegisterCache;
+use dio::{Dio, DioMapping, LoRaDio0};
+pub use irq::{RadioEvent, RadioEvents, RadioEventsIter};
+#[cfg(feature = "linux")]
+pub use linux::{LinuxError, LinuxLoRa, GPIO_CHIP, SPI_SPEED_HZ};
+pub use pins::{AntennaMode, AntennaSwitch, NoPin, NoSwitch, Pins, RfSwitch, RfSwitchError};
 use register::AsAddr;
-use register::{FskDataModulationShaping, FskRampUpRamDown};
-use register::{IRQMask, PaConfig, Register};
+pub use register::{FskDataModulationShaping, PaRamp};
+use register::{FskRegister, Register};
+pub use register::{IRQMask, LnaGain};
 
 /// Provides the necessary SPI mode configuration for the radio
 /// Note that this may vary by device. Modules other than the RFM95
 /// module requires may require `embedded_hal::spi::MODE_3` instead.
 pub const MODE: Mode = embedded_hal::spi::MODE_0;
 
-use crate::radio_traits::EmbeddedRadio;
+use crate::clock::{Clock, TimestampedPacket};
+use crate::radio_traits::{
+    Capabilities, ConfigurableRadio, EmbeddedRadio, LoRaDataRate, OversizedPayload, PaSelect,
+    RadioError, RadioErrorKind, LORA_BANDWIDTHS,
+};
 
 /// Provides high-level access to Semtech SX1276/77/78/79 based boards connected to a Raspberry Pi
-pub struct LoRa<SPI, CS, RESET> {
+///
+/// The reset pin, DIO inputs and RF switch are optional: see `without_reset` and `with_pins`.
+pub struct LoRa<SPI, CS, RESET, DIO0 = NoPin, DIO1 = NoPin, SW = NoSwitch> {
     spi: SPI,
     cs: CS,
     reset: RESET,
-    frequency: i64,
-    pub explicit_header: bool,
+    dio0: DIO0,
+    dio1: DIO1,
+    antenna_switch: SW,
+    /// Carrier frequency in Hz, without the AFC correction.
+    frequency_hz: i64,
+    oscillator: Oscillator,
+    pa_select: PaSelect,
+    header_mode: HeaderMode,
     pub mode: RadioMode,
+    /// Packet IRQ flags cleared by `handle_interrupt` whose packet has not been read yet.
+    pending_rx_flags: u8,
+    /// Instant of the pending `RxDone`, if it was handled by `handle_interrupt_at`.
+    rx_done_us: Option<u64>,
+    afc_enabled: bool,
+    /// Correction applied on top of `frequency_hz` by automatic frequency correction, in Hz.
+    afc_offset: i64,
+    /// Configuration registers as last read or written, used by the shadow registers.
+    cache: RegisterCache,
+    /// Configuration registers as last written in LoRa mode, restored by `recover`.
+    snapshot: RegisterCache,
+    /// Whether configuration registers are read from `cache` instead of the chip.
+    shadow_registers: bool,
+    /// Set by `put_in_fsk_mode`, where the LoRa configuration registers have a different meaning.
+    fsk_mode: bool,
 }
 
 #[derive(Debug)]
-pub enum Error<SPI, CS, RESET> {
+/// Error of the driver. `SW` is the error of the RF switch and `DIO` the error of the DIO input read by the
+/// method which failed, both `Infallible` when there is none.
+pub enum Error<SPI, CS, RESET, SW = Infallible, DIO = Infallible> {
     Uninformative,
     VersionMismatch(u8),
     CS(CS),
     Reset(RESET),
     Spi(SPI),
     Transmitting,
+    PayloadTooLarge(usize),
+    PayloadLengthMismatch {
+        expected: u8,
+        actual: usize,
+    },
+    InvalidOcp(u8),
+    /// The radio did not signal `TxDone` in time.
+    TxTimeout,
+    /// The DIO pin could not be read.
+    Dio(DIO),
+    /// A scheduled transmission could not start in time, having been late by this many microseconds.
+    MissedDeadline(u64),
+    /// The setting is outside of what the radio supports.
+    Unsupported,
+    /// The RF switch could not be set.
+    AntennaSwitch(SW),
 }
 
 use Error::*;
 
+/// Error of the methods of `LoRa<SPI, CS, RESET, DIO0, DIO1, SW>` whose SPI bus fails with `E`. `DIO` is the
+/// error of the DIO input read by the method, if any.
+pub type LoRaError<E, CS, RESET, SW, DIO = Infallible> = Error<
+    E,
+    <CS as OutputPin>::Error,
+    <RESET as OutputPin>::Error,
+    <SW as AntennaSwitch>::Error,
+    DIO,
+>;
+
+impl<SPI, CS, RESET, SW> Error<SPI, CS, RESET, SW> {
+    /// Converts an error raised without reading a DIO pin into the error of a method reading one.
+    pub(crate) fn with_dio<DIO>(self) -> Error<SPI, CS, RESET, SW, DIO> {
+        match self {
+            Uninformative => Uninformative,
+            VersionMismatch(version) => VersionMismatch(version),
+            CS(e) => CS(e),
+            Reset(e) => Reset(e),
+            Spi(e) => Spi(e),
+            Transmitting => Transmitting,
+            PayloadTooLarge(len) => PayloadTooLarge(len),
+            PayloadLengthMismatch { expected, actual } => {
+                PayloadLengthMismatch { expected, actual }
+            }
+            InvalidOcp(ocp) => InvalidOcp(ocp),
+            TxTimeout => TxTimeout,
+            Dio(never) => match never {},
+            MissedDeadline(late) => MissedDeadline(late),
+            Unsupported => Unsupported,
+            AntennaSwitch(e) => AntennaSwitch(e),
+        }
+    }
+}
+
+impl<SPI, CS, RESET, SW, DIO> From<OversizedPayload> for Error<SPI, CS, RESET, SW, DIO> {
+    fn from(payload: OversizedPayload) -> Self {
+        PayloadTooLarge(payload.0)
+    }
+}
+
+impl<SPI: Debug, CS: Debug, RESET: Debug, SW: Debug, DIO: Debug> RadioError
+    for Error<SPI, CS, RESET, SW, DIO>
+{
+    fn kind(&self) -> RadioErrorKind {
+        match self {
+            Uninformative => RadioErrorKind::Other,
+            VersionMismatch(_) => RadioErrorKind::Device,
+            CS(_) | Reset(_) | Spi(_) | Dio(_) | AntennaSwitch(_) => RadioErrorKind::Bus,
+            Transmitting => RadioErrorKind::Busy,
+            PayloadTooLarge(_) | PayloadLengthMismatch { .. } => RadioErrorKind::InvalidPayload,
+            InvalidOcp(_) | Unsupported => RadioErrorKind::InvalidSetting,
+            TxTimeout | MissedDeadline(_) => RadioErrorKind::Timeout,
+        }
+    }
+}
+
 #[cfg(not(feature = "version_0x09"))]
 const VERSION_CHECK: u8 = 0x12;
 
 #[cfg(feature = "version_0x09")]
 const VERSION_CHECK: u8 = 0x09;
 
+/// IRQ flags describing a received packet, cleared together once the packet has been read.
+const PACKET_IRQ_FLAGS: u8 =
+    IRQMask::RxDone as u8 | IRQMask::PayloadCrcError as u8 | IRQMask::ValidHeader as u8;
+
 /// Implement embedded_radio traits
-impl<SPI, CS, RESET, E> EmbeddedRadio for LoRa<SPI, CS, RESET>
+impl<SPI, CS, RESET, DIO0, DIO1, SW, E> EmbeddedRadio for LoRa<SPI, CS, RESET, DIO0, DIO1, SW>
 where
     SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
     CS: OutputPin,
     RESET: OutputPin,
+    SW: AntennaSwitch,
 {
-    type Error = Error<E, CS::Error, RESET::Error>;
+    type Error = LoRaError<E, CS, RESET, SW>;
 
     /// Blocking version of transmit_payload().
     fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
@@ -64,86 +190,143 @@ where
 
     /// Transmits up to 255 bytes of data. Takes a u8 slice of up to 255 elements. Returns () on success.
     fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
-        if self.transmitting()? {
-            Err(Transmitting)
-        } else {
-            self.set_mode(RadioMode::Stdby)?;
+        self.transmit_vectored(&[payload])
+    }
 
-            self.write_register(Register::IrqFlags, 0)?;
-            self.write_register(Register::FifoAddrPtr, 0)?;
-            self.write_register(Register::PayloadLength, 0)?;
-            for &byte in payload.iter().take(255) {
-                self.write_register(Register::Fifo, byte)?;
-            }
-            self.write_register(Register::PayloadLength, payload.len().min(255) as u8)?;
-            self.set_mode(RadioMode::Tx)?;
-            Ok(())
-        }
+    /// Transmits the concatenation of up to 255 bytes of data, streaming each slice into the FIFO in order.
+    /// Returns `PayloadTooLarge` with the combined length if it exceeds 255 bytes, and
+    /// `PayloadLengthMismatch` if it differs from the fixed length of `HeaderMode::Implicit`.
+    fn transmit_vectored(&mut self, payloads: &[&[u8]]) -> Result<(), Self::Error> {
+        self.load_fifo(payloads)?;
+        self.set_mode(RadioMode::Tx)
+    }
+
+    /// Checks `RxDone` in the IRQ register, or left pending by `handle_interrupt()`. A packet which has
+    /// arrived is lost if the radio starts transmitting before it is read.
+    fn packet_pending(&mut self) -> Result<bool, Self::Error> {
+        let flags = self.pending_rx_flags | self.read_register(Register::IrqFlags)?;
+        Ok(flags & IRQMask::RxDone.addr() != 0)
     }
 
     /// Returns Some Vec with a capacity of 255 bytes, if a packet has arrived. If no packet has arrived, None
     /// is returned. Errors result from hardware faults.
     fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, Self::Error> {
-        self.set_mode(RadioMode::RxContinuous)?;
+        let mut packet = Vec::new();
+        // cannot fail, the capacity is 255
+        packet.resize_default(255).ok();
+
+        Ok(self.read_packet_into(&mut packet)?.map(|packet_size| {
+            packet.truncate(packet_size);
+            packet
+        }))
+    }
+
+    /// Reads an arrived packet straight from the FIFO into `buffer` and returns its size. Bytes which
+    /// do not fit into `buffer` are left in the FIFO and discarded.
+    fn read_packet_into(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        self.start_receive()?;
         if let Some(packet_size) = self.check_irq()? {
             // IRQ already cleared
-            let mut buffer = Vec::new();
-
             let fifo_addr = self.read_register(Register::FifoRxCurrentAddr)?;
             self.write_register(Register::FifoAddrPtr, fifo_addr)?;
 
-            for _ in 0..packet_size {
-                let byte = self.read_register(Register::Fifo)?;
-                // memory safety guaranteed here, packet size cannot be more than 255
-                buffer.push(byte).ok();
-            }
+            let len = packet_size.min(buffer.len());
+            self.read_fifo(&mut buffer[..len])?;
             self.write_register(Register::FifoAddrPtr, 0)?;
 
-            Ok(Some(buffer))
+            Ok(Some(packet_size))
         } else {
             Ok(None)
         }
     }
 
-    /// Polls read_packet() for timeout (in milliseconds). Same return type.
-    fn read_packet_timeout<DELAY: DelayMs<u16>>(
-        &mut self,
-        timeout_ms: i32,
-        delay: &mut DELAY,
-    ) -> Result<Option<Vec<u8, 255>>, Self::Error> {
-        let mut count = 0;
-
-        let packet = loop {
-            let packet = self.read_packet()?;
-
-            if packet.is_some() {
-                break packet;
-            }
-
-            if count >= timeout_ms {
-                break None;
-            }
-
-            count += 1;
-            delay.delay_ms(1);
-        };
-
-        Ok(packet)
+    /// Half duplex, with RSSI, SNR and channel activity detection (`RadioMode::Cad`).
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            rssi: true,
+            snr: true,
+            channel_activity_detection: true,
+            ..Capabilities::new(255)
+        }
     }
 
     /// Returns true if the radio is currently transmitting a packet.
     fn transmitting(&mut self) -> Result<bool, Self::Error> {
-        if (self.read_register(Register::OpMode)? & RadioMode::Tx.addr()) == RadioMode::Tx.addr() {
+        // the mode bits of channel activity detection include those of Tx
+        if self.read_register(Register::OpMode)?.get_bits(0..3) == RadioMode::Tx.addr() {
             Ok(true)
         } else {
-            if (self.read_register(Register::IrqFlags)? & IRQMask::TxDone.addr()) == 1 {
+            if (self.read_register(Register::IrqFlags)? & IRQMask::TxDone.addr()) != 0 {
                 self.write_register(Register::IrqFlags, IRQMask::TxDone.addr())?;
             }
+            if matches!(self.mode, RadioMode::Tx) {
+                self.tx_finished()?;
+            }
             Ok(false)
         }
     }
 }
 
+impl<SPI, CS, RESET, DIO0, DIO1, SW, E> ConfigurableRadio for LoRa<SPI, CS, RESET, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    SW: AntennaSwitch,
+{
+    type DataRate = LoRaDataRate;
+    /// Bandwidth in Hz.
+    type Bandwidth = u32;
+
+    /// The range of the SX1276; SX1277/78/79 based boards and the modules' matching networks cover
+    /// only part of it.
+    fn frequency_range(&self) -> RangeInclusive<u32> {
+        137_000_000..=1_020_000_000
+    }
+
+    fn set_frequency_hz(&mut self, frequency: u32) -> Result<(), Self::Error> {
+        if !self.frequency_range().contains(&frequency) {
+            return Err(Unsupported);
+        }
+        self.tune(i64::from(frequency))
+    }
+
+    /// Depends on the power amplifier selected in `Config`.
+    fn tx_power_range(&self) -> RangeInclusive<i8> {
+        self.pa_select.tx_power_range()
+    }
+
+    fn set_tx_power_dbm(&mut self, power: i8) -> Result<i8, Self::Error> {
+        if !self.tx_power_range().contains(&power) {
+            return Err(Unsupported);
+        }
+        self.set_tx_power(power, self.pa_select)
+    }
+
+    fn supports_data_rate(&self, rate: &LoRaDataRate) -> bool {
+        rate.is_supported()
+    }
+
+    fn set_data_rate(&mut self, rate: LoRaDataRate) -> Result<(), Self::Error> {
+        if !self.supports_data_rate(&rate) {
+            return Err(Unsupported);
+        }
+        self.set_spreading_factor(rate.spreading_factor)?;
+        self.set_coding_rate_4(rate.coding_rate)
+    }
+
+    fn supports_bandwidth(&self, bandwidth: &u32) -> bool {
+        LORA_BANDWIDTHS.contains(bandwidth)
+    }
+
+    fn set_bandwidth(&mut self, bandwidth: u32) -> Result<(), Self::Error> {
+        if !self.supports_bandwidth(&bandwidth) {
+            return Err(Unsupported);
+        }
+        self.set_signal_bandwidth(i64::from(bandwidth))
+    }
+}
+
 impl<SPI, CS, RESET, E> LoRa<SPI, CS, RESET>
 where
     SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
@@ -152,6 +335,7 @@ where
 {
     /// Builds and returns a new instance of the radio. Only one instance of the radio should exist at a time.
     /// This also preforms a hardware reset of the module and then puts it in standby.
+    /// The board is expected to use the reference 32 MHz crystal, see `with_config` otherwise.
     pub fn new<DELAY: DelayMs<u16>>(
         spi: SPI,
         cs: CS,
@@ -159,13 +343,121 @@ where
         frequency: i64,
         delay: &mut DELAY,
     ) -> Result<Self, Error<E, CS::Error, RESET::Error>> {
+        Self::with_config(spi, cs, reset, Config::new(frequency), delay)
+    }
+
+    /// Builds and returns a new instance of the radio from a `Config`, as `new` does.
+    pub fn with_config<DELAY: DelayMs<u16>>(
+        spi: SPI,
+        cs: CS,
+        reset: RESET,
+        config: Config,
+        delay: &mut DELAY,
+    ) -> Result<Self, Error<E, CS::Error, RESET::Error>> {
+        Self::with_pins(spi, cs, reset, Pins::none(), config, delay)
+    }
+}
+
+impl<SPI, CS, E> LoRa<SPI, CS, NoPin>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+{
+    /// Builds a radio whose NRESET is not connected to a pin, e.g. tied to the MCU reset. The radio is not
+    /// reset, only put in sleep mode and configured, and `recover` can only rewrite its registers.
+    pub fn without_reset<DELAY: DelayMs<u16>>(
+        spi: SPI,
+        cs: CS,
+        config: Config,
+        delay: &mut DELAY,
+    ) -> Result<Self, Error<E, CS::Error, Infallible>> {
+        Self::with_config(spi, cs, NoPin, config, delay)
+    }
+}
+
+impl<SPI, CS, RESET, DIO0, DIO1, SW, E> LoRa<SPI, CS, RESET, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    DIO0: InputPin,
+    SW: AntennaSwitch,
+{
+    /// Returns true if the DIO0 input given to `with_pins` is high.
+    pub fn dio0_is_high(&self) -> Result<bool, LoRaError<E, CS, RESET, SW, DIO0::Error>> {
+        self.dio0.is_high().map_err(Dio)
+    }
+
+    /// Like `transmit_and_wait_with_dio0`, waiting on the DIO0 input given to `with_pins`.
+    pub fn transmit_and_wait_on_dio0<DELAY: DelayMs<u16>>(
+        &mut self,
+        payload: &[u8],
+        timeout_ms: Option<u32>,
+        delay: &mut DELAY,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW, DIO0::Error>> {
+        let (timeout_ms, mapping) = self
+            .start_transmit_with_dio0(payload, timeout_ms)
+            .map_err(Error::with_dio)?;
+        let done = self.wait_tx_done(timeout_ms, delay, |lora| {
+            let high = lora.dio0_is_high()?;
+            lora.take_tx_done(high).map_err(Error::with_dio)
+        });
+        let restored = self.restore_dio_mapping(Dio::Dio0, mapping);
+        done.and(restored.map_err(Error::with_dio))
+    }
+}
+
+impl<SPI, CS, RESET, DIO0, DIO1, SW, E> LoRa<SPI, CS, RESET, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    DIO1: InputPin,
+    SW: AntennaSwitch,
+{
+    /// Returns true if the DIO1 input given to `with_pins` is high.
+    pub fn dio1_is_high(&self) -> Result<bool, LoRaError<E, CS, RESET, SW, DIO1::Error>> {
+        self.dio1.is_high().map_err(Dio)
+    }
+}
+
+impl<SPI, CS, RESET, DIO0, DIO1, SW, E> LoRa<SPI, CS, RESET, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    SW: AntennaSwitch,
+{
+    /// Builds a radio as `with_config` does, together with its DIO inputs and RF switch. Pass `NoPin` as
+    /// `reset` if NRESET is not connected.
+    pub fn with_pins<DELAY: DelayMs<u16>>(
+        spi: SPI,
+        cs: CS,
+        reset: RESET,
+        pins: Pins<DIO0, DIO1, SW>,
+        config: Config,
+        delay: &mut DELAY,
+    ) -> Result<Self, LoRaError<E, CS, RESET, SW>> {
         let mut sx127x = LoRa {
             spi,
             cs,
             reset,
-            frequency,
-            explicit_header: true,
+            dio0: pins.dio0,
+            dio1: pins.dio1,
+            antenna_switch: pins.antenna_switch,
+            frequency_hz: config.frequency * 1_000_000,
+            oscillator: config.oscillator,
+            pa_select: config.pa_select,
+            header_mode: HeaderMode::Explicit,
             mode: RadioMode::Sleep,
+            pending_rx_flags: 0,
+            rx_done_us: None,
+            afc_enabled: false,
+            afc_offset: 0,
+            cache: RegisterCache::new(),
+            snapshot: RegisterCache::new(),
+            shadow_registers: false,
+            fsk_mode: false,
         };
         sx127x.reset.set_low().map_err(Reset)?;
         delay.delay_ms(10);
@@ -174,12 +466,13 @@ where
         let version = sx127x.read_register(Register::Version)?;
         if version == VERSION_CHECK {
             sx127x.set_mode(RadioMode::Sleep)?;
-            sx127x.set_frequency(frequency)?;
+            let mut tcxo = sx127x.read_register(Register::Tcxo)?;
+            tcxo.set_bit(4, matches!(config.oscillator, Oscillator::Tcxo(_)));
+            sx127x.write_register(Register::Tcxo, tcxo)?;
+            sx127x.set_frequency(config.frequency)?;
             sx127x.write_register(Register::FifoTxBaseAddr, 0)?;
             sx127x.write_register(Register::FifoRxBaseAddr, 0)?;
-            let lna = sx127x.read_register(Register::Lna)?;
-            sx127x.write_register(Register::Lna, lna | 0x03)?;
-            sx127x.write_register(Register::ModemConfig3, 0x04)?;
+            sx127x.set_lna(LnaGain::Agc, true)?;
             sx127x.set_mode(RadioMode::Stdby)?;
             sx127x.cs.set_high().map_err(CS)?;
             Ok(sx127x)
@@ -193,26 +486,188 @@ where
         (self.spi, self.cs, self.reset)
     }
 
-    /// Check the radio's IRQ registers for a new packet, and only return it's size if one has arrived.
-    fn check_irq(&mut self) -> Result<Option<usize>, Error<E, CS::Error, RESET::Error>> {
-        let packet_ready: bool = self.read_register(Register::IrqFlags)?.get_bit(6);
+    /// Return ownership of lora driver component elements, including the pins given to `with_pins`.
+    pub fn decompose_with_pins(self) -> (SPI, CS, RESET, Pins<DIO0, DIO1, SW>) {
+        let pins = Pins {
+            dio0: self.dio0,
+            dio1: self.dio1,
+            antenna_switch: self.antenna_switch,
+        };
+        (self.spi, self.cs, self.reset, pins)
+    }
 
-        if packet_ready {
-            self.clear_irq()?;
+    /// Check the radio's IRQ registers for a new packet, and only return it's size if one has arrived.
+    fn check_irq(&mut self) -> Result<Option<usize>, LoRaError<E, CS, RESET, SW>> {
+        if self.take_rx_done()? {
             Ok(Some(self.read_register(Register::RxNbBytes)? as usize))
         } else {
             Ok(None)
         }
     }
 
-    /// Clears the radio's IRQ registers.
-    fn clear_irq(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        let irq_flags = self.read_register(Register::IrqFlags)?;
-        self.write_register(Register::IrqFlags, irq_flags)?;
+    /// Checks for `RxDone`, either in the IRQ register or left pending by `handle_interrupt()`, and
+    /// clears the flags belonging to the received packet. Other flags are left for their owners.
+    /// With automatic frequency correction on, packets with a valid CRC feed the frequency tracker.
+    fn take_rx_done(&mut self) -> Result<bool, LoRaError<E, CS, RESET, SW>> {
+        // flags left pending were cleared on the chip already; clearing them again could drop a
+        // packet which arrived since
+        let packet_flags = if self.pending_rx_flags & IRQMask::RxDone.addr() != 0 {
+            self.pending_rx_flags
+        } else {
+            let flags = self.read_register(Register::IrqFlags)? & PACKET_IRQ_FLAGS;
+            if flags & IRQMask::RxDone.addr() != 0 {
+                self.write_register(Register::IrqFlags, flags)?;
+            }
+            flags
+        };
+
+        if packet_flags & IRQMask::RxDone.addr() != 0 {
+            self.pending_rx_flags = 0;
+            self.rx_done_us = None;
+            if self.afc_enabled && packet_flags & IRQMask::PayloadCrcError.addr() == 0 {
+                self.track_frequency_error()?;
+            }
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Reads and clears the radio's IRQ flags once, returning the events they signal. Call this
+    /// from the DIO interrupt handler, or after waking from it. Only the flags that were read are
+    /// cleared, so events raised in between are reported by the next call. A packet announced by
+    /// `RadioEvent::RxDone` can still be fetched afterwards with `read_packet()`.
+    pub fn handle_interrupt(&mut self) -> Result<RadioEvents, LoRaError<E, CS, RESET, SW>> {
+        self.take_events(None)
+    }
+
+    /// Like `handle_interrupt()`, also recording `irq_us`, the instant the DIO pin fired on the
+    /// clock later passed to `read_packet_timestamped()`, as the arrival time of the packet.
+    pub fn handle_interrupt_at(
+        &mut self,
+        irq_us: u64,
+    ) -> Result<RadioEvents, LoRaError<E, CS, RESET, SW>> {
+        self.take_events(Some(irq_us))
+    }
+
+    fn take_events(
+        &mut self,
+        irq_us: Option<u64>,
+    ) -> Result<RadioEvents, LoRaError<E, CS, RESET, SW>> {
+        let flags = self.read_register(Register::IrqFlags)?;
+        if flags != 0 {
+            self.write_register(Register::IrqFlags, flags)?;
+        }
+
+        if flags & IRQMask::RxDone.addr() != 0 {
+            self.pending_rx_flags = flags & PACKET_IRQ_FLAGS;
+            self.rx_done_us = irq_us;
+        }
+        // the radio went back to standby on its own
+        if flags & IRQMask::TxDone.addr() != 0 && matches!(self.mode, RadioMode::Tx) {
+            self.tx_finished()?;
+        }
+
+        Ok(RadioEvents::from_flags(flags))
+    }
+
+    /// Reads a packet as `read_packet()` does, along with the instant it arrived on `clock`. That
+    /// is the instant recorded by `handle_interrupt_at()` if the packet was announced there, and
+    /// otherwise the time of this poll, which is late by at most the polling interval.
+    pub fn read_packet_timestamped<C: Clock>(
+        &mut self,
+        clock: &C,
+    ) -> Result<Option<TimestampedPacket>, LoRaError<E, CS, RESET, SW>> {
+        let rx_done_us = self.rx_done_us;
+        let polled_us = clock.now_us();
+
+        Ok(self.read_packet()?.map(|payload| TimestampedPacket {
+            payload,
+            rx_done_us: rx_done_us.unwrap_or(polled_us),
+        }))
+    }
+
+    /// Masks or unmasks a LoRa IRQ source. A masked source neither raises its flag in the IRQ
+    /// register nor toggles the DIO pin it is mapped to, so it is never reported by
+    /// `handle_interrupt()`. `read_packet()` relies on `RxDone`, which must stay enabled to receive.
+    pub fn set_irq_enabled(
+        &mut self,
+        irq: IRQMask,
+        enabled: bool,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mask = self.read_register(Register::IrqFlagsMask)?;
+        let mask = if enabled {
+            mask & !irq.addr()
+        } else {
+            mask | irq.addr()
+        };
+        self.write_register(Register::IrqFlagsMask, mask)?;
+
+        Ok(())
+    }
+
+    /// Enables exactly the given IRQ sources and masks all others.
+    pub fn set_enabled_irqs(
+        &mut self,
+        irqs: &[IRQMask],
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let enabled = irqs.iter().fold(0, |enabled, irq| enabled | irq.addr());
+        self.write_register(Register::IrqFlagsMask, !enabled)?;
+
+        Ok(())
+    }
+
+    /// Returns true if the IRQ source is not masked.
+    pub fn irq_enabled(&mut self, irq: IRQMask) -> Result<bool, LoRaError<E, CS, RESET, SW>> {
+        Ok(self.read_register(Register::IrqFlagsMask)? & irq.addr() == 0)
+    }
+
+    /// Routes a function to its DIO pin, as `set_dio_mapping()` does, and unmasks the IRQ source
+    /// behind it so the pin actually fires.
+    pub fn enable_interrupt<M: DioMapping>(
+        &mut self,
+        mapping: M,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.set_dio_mapping(mapping)?;
+        if let Some(irq) = mapping.irq() {
+            self.set_irq_enabled(irq, true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Puts the radio in continuous receive mode, unless it is already listening. A packet being
+    /// received in single receive mode is left to complete. `read_packet()` calls this on every
+    /// poll, so it only touches the radio when reception has to be (re)started.
+    pub fn start_receive(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mode = self.mode;
+        match mode {
+            RadioMode::RxContinuous => Ok(()),
+            RadioMode::RxSingle if self.modem_status()?.receiving() => Ok(()),
+            _ => self.set_mode(RadioMode::RxContinuous),
+        }
+    }
 
+    /// Stops receiving and puts the radio in standby. Does nothing if it was not receiving.
+    pub fn stop_receive(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        if self.is_listening() {
+            self.set_mode(RadioMode::Stdby)?;
+        }
         Ok(())
     }
 
+    /// Returns true if the driver put the radio in a receive mode and has not left it since.
+    pub fn is_listening(&self) -> bool {
+        matches!(self.mode, RadioMode::RxContinuous | RadioMode::RxSingle)
+    }
+
+    /// Reads the live status of the LoRa modem.
+    pub fn modem_status(&mut self) -> Result<ModemStatus, LoRaError<E, CS, RESET, SW>> {
+        Ok(ModemStatus {
+            flags: self.read_register(Register::ModemStat)?,
+        })
+    }
+
     /// Blocks the current thread, returning the size of a packet if one is received or an error is the
     /// task timed out. The timeout can be supplied with None to make it poll indefinitely or
     /// with `Some(timeout_in_milliseconds)`
@@ -220,13 +675,13 @@ where
         &mut self,
         timeout_ms: Option<i32>,
         delay: &mut DELAY,
-    ) -> Result<usize, Error<E, CS::Error, RESET::Error>> {
-        self.set_mode(RadioMode::RxContinuous)?;
+    ) -> Result<usize, LoRaError<E, CS, RESET, SW>> {
+        self.start_receive()?;
         match timeout_ms {
             Some(value) => {
                 let mut count = 0;
                 let packet_ready = loop {
-                    let packet_ready = self.read_register(Register::IrqFlags)?.get_bit(6);
+                    let packet_ready = self.take_rx_done()?;
                     if count >= value || packet_ready {
                         break packet_ready;
                     }
@@ -234,157 +689,512 @@ where
                     delay.delay_ms(1);
                 };
                 if packet_ready {
-                    self.clear_irq()?;
                     Ok(self.read_register(Register::RxNbBytes)? as usize)
                 } else {
                     Err(Uninformative)
                 }
             }
             None => {
-                while !self.read_register(Register::IrqFlags)?.get_bit(6) {
+                while !self.take_rx_done()? {
                     delay.delay_ms(100);
                 }
-                self.clear_irq()?;
                 Ok(self.read_register(Register::RxNbBytes)? as usize)
             }
         }
     }
 
-    pub fn set_dio0_tx_done(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        self.write_register(Register::DioMapping1, 0b01_00_00_00)?;
-
-        Ok(())
-    }
+    /// Checks the packet and writes it into the FIFO with the radio in standby, ready to be sent.
+    fn load_fifo(&mut self, payloads: &[&[u8]]) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let len: usize = payloads.iter().map(|payload| payload.len()).sum();
+        if len > 255 {
+            return Err(PayloadTooLarge(len));
+        }
+        if let HeaderMode::Implicit { len: expected, .. } = self.header_mode {
+            if len != expected as usize {
+                return Err(PayloadLengthMismatch {
+                    expected,
+                    actual: len,
+                });
+            }
+        }
 
-    /// Sets the transmit power and pin. Levels can range from 0-14 when the output
-    /// pin = 0(RFO), and form 0-20 when output pin = 1(PaBoost). Power is in dB.
-    /// Default value is `17`.
-    pub fn set_tx_power(
-        &mut self,
-        level: i32,
-        output_pin: u8,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        if PaConfig::PaOutputRfoPin.addr() == output_pin {
-            // RFO
-            let level = level.clamp(0, 14);
-
-            self.write_register(Register::PaConfig, (0x70 | level) as u8)?;
+        if self.transmitting()? {
+            Err(Transmitting)
         } else {
-            // PA BOOST
-            let mut level = level.clamp(2, 20);
-
-            if level > 17 {
-                // subtract 3 from level, so 18 - 20 maps to 15 - 17
-                level -= 3;
+            self.set_mode(RadioMode::Stdby)?;
 
-                // High Power +20 dBm Operation (Semtech SX1276/77/78/79 5.4.3.)
-                self.write_register(Register::PaDac, 0x87)?;
-                self.set_ocp(140)?;
-            } else {
-                //Default value PA_HF/LF or +17dBm
-                self.write_register(Register::PaDac, 0x84)?;
-                self.set_ocp(100)?;
-            }
-            level -= 2;
-            self.write_register(Register::PaConfig, PaConfig::PaBoost.addr() | level as u8)?;
+            self.write_register(Register::IrqFlags, 0)?;
+            self.write_register(Register::FifoAddrPtr, 0)?;
+            self.write_register(Register::PayloadLength, 0)?;
+            self.write_fifo(payloads)?;
+            self.write_register(Register::PayloadLength, len as u8)?;
+            Ok(())
         }
-
-        Ok(())
     }
 
-    /// Sets the over current protection on the radio(mA).
-    pub fn set_ocp(&mut self, ma: u8) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        let mut ocp_trim: u8 = 27;
-
-        if ma <= 120 {
-            ocp_trim = (ma - 45) / 5;
-        } else if ma <= 240 {
-            ocp_trim = (ma + 30) / 10;
-        }
-        self.write_register(Register::Ocp, 0x20 | (0x1F & ocp_trim))?;
+    /// Loads the payload into the FIFO and starts the frequency synthesizer, so that
+    /// `start_prepared_transmit` only has to switch the radio to Tx. Fails like `transmit_payload`.
+    pub fn prepare_transmit(&mut self, payload: &[u8]) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.load_fifo(&[payload])?;
+        self.set_mode(RadioMode::FsTx)
+    }
 
-        Ok(())
+    /// Sends the payload loaded by `prepare_transmit`, with a single register write.
+    pub fn start_prepared_transmit(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.set_mode(RadioMode::Tx)
     }
 
-    /// Sets the state of the radio. Default mode after initiation is `Standby`.
-    pub fn set_mode(&mut self, mode: RadioMode) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        if self.explicit_header {
-            self.set_explicit_header_mode()?;
-        } else {
-            self.set_implicit_header_mode()?;
+    /// Transmits the payload at `at_us` on `clock`, blocking until then. The payload is loaded and
+    /// the synthesizer locked straight away, then the clock is polled until the deadline, so the
+    /// transmission starts within one poll of `clock` and one SPI register write of `at_us`,
+    /// followed by the PA ramp-up set with `set_pa_ramp`. Returns `MissedDeadline` and leaves the
+    /// radio in standby if the deadline had already passed once the payload was loaded.
+    pub fn schedule_transmit<C: Clock>(
+        &mut self,
+        payload: &[u8],
+        at_us: u64,
+        clock: &C,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.prepare_transmit(payload)?;
+        let now = clock.now_us();
+        if now > at_us {
+            self.set_mode(RadioMode::Stdby)?;
+            return Err(MissedDeadline(now - at_us));
         }
-        self.write_register(
-            Register::OpMode,
-            RadioMode::LongRangeMode.addr() | mode.addr(),
-        )?;
-
-        self.mode = mode;
-        Ok(())
+        while clock.now_us() < at_us {}
+        self.start_prepared_transmit()
     }
 
-    /// Sets the frequency of the radio. Values are in megahertz.
-    /// I.E. 915 MHz must be used for North America. Check regulation for your area.
-    pub fn set_frequency(&mut self, freq: i64) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        self.frequency = freq;
-        // calculate register values
-        let base = 1;
-        let frf = (freq * (base << 19)) / 32;
-        // write registers
-        self.write_register(Register::FrfMsb, ((frf & 0x00FF_0000) >> 16) as u8)?;
-        self.write_register(Register::FrfMid, ((frf & 0x0000_FF00) >> 8) as u8)?;
-        self.write_register(Register::FrfLsb, (frf & 0x0000_00FF) as u8)?;
+    /// Returns how long a packet of `len` bytes takes to transmit with the current modem
+    /// settings, in microseconds.
+    pub fn time_on_air_us(&mut self, len: usize) -> Result<u32, LoRaError<E, CS, RESET, SW>> {
+        let sf = i64::from(self.get_spreading_factor()?.clamp(6, 12));
+        let bw = self.get_signal_bandwidth()?.max(7_800);
+        let config_1 = self.read_register(Register::ModemConfig1)?;
+        let cr = i64::from(config_1.get_bits(1..4).clamp(1, 4));
+        let crc = i64::from(self.read_register(Register::ModemConfig2)?.get_bit(2));
+        let ldo = i64::from(self.read_register(Register::ModemConfig3)?.get_bit(3));
+        let implicit = i64::from(matches!(self.header_mode, HeaderMode::Implicit { .. }));
+        let preamble = i64::from(self.read_register(Register::PreambleMsb)?) << 8
+            | i64::from(self.read_register(Register::PreambleLsb)?);
+
+        // Section 4.1.1.7, counted in quarter symbols
+        let bits = 8 * len as i64 - 4 * sf + 28 + 16 * crc - 20 * implicit;
+        let divisor = 4 * (sf - 2 * ldo);
+        let blocks = ((bits + divisor - 1) / divisor).max(0);
+        let quarter_symbols = 4 * preamble + 17 + 4 * (8 + blocks * (cr + 4));
+
+        // saturate rather than wrap past 71 minutes, so timeouts derived from it stay long enough
+        Ok(u32::try_from(quarter_symbols * (1 << sf) * 1_000_000 / (4 * bw)).unwrap_or(u32::MAX))
+    }
 
-        Ok(())
+    /// Transmits the payload and waits until the radio reports `TxDone`, polling it over SPI every
+    /// millisecond. `timeout_ms` defaults to the time on air of the payload plus a margin; if the
+    /// radio has not finished by then it is put in standby and `TxTimeout` is returned.
+    pub fn transmit_and_wait<DELAY: DelayMs<u16>>(
+        &mut self,
+        payload: &[u8],
+        timeout_ms: Option<u32>,
+        delay: &mut DELAY,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let timeout_ms = self.tx_timeout_ms(payload.len(), timeout_ms)?;
+        self.transmit_payload(payload)?;
+        self.wait_tx_done(timeout_ms, delay, |lora| Ok(!lora.transmitting()?))
     }
 
-    /// Sets the radio to use an explicit header. Default state is `ON`.
-    fn set_explicit_header_mode(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        let reg_modem_config_1 = self.read_register(Register::ModemConfig1)?;
-        self.write_register(Register::ModemConfig1, reg_modem_config_1 & 0xfe)?;
-        self.explicit_header = true;
-        Ok(())
+    /// Like `transmit_and_wait`, but maps DIO0 to `TxDone` and waits for the pin to go high
+    /// instead of polling the radio over SPI. The previous mapping of DIO0 is restored afterwards,
+    /// so a receiver waiting on `RxDone` keeps working.
+    pub fn transmit_and_wait_with_dio0<DELAY: DelayMs<u16>, DIO: InputPin>(
+        &mut self,
+        payload: &[u8],
+        timeout_ms: Option<u32>,
+        delay: &mut DELAY,
+        dio0: &DIO,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW, DIO::Error>> {
+        let (timeout_ms, mapping) = self
+            .start_transmit_with_dio0(payload, timeout_ms)
+            .map_err(Error::with_dio)?;
+        let done = self.wait_tx_done(timeout_ms, delay, |lora| {
+            let high = dio0.is_high().map_err(Dio)?;
+            lora.take_tx_done(high).map_err(Error::with_dio)
+        });
+        let restored = self.restore_dio_mapping(Dio::Dio0, mapping);
+        done.and(restored.map_err(Error::with_dio))
     }
 
-    /// Sets the radio to use an implicit header. Default state is `OFF`.
-    fn set_implicit_header_mode(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        let reg_modem_config_1 = self.read_register(Register::ModemConfig1)?;
-        self.write_register(Register::ModemConfig1, reg_modem_config_1 & 0x01)?;
-        self.explicit_header = false;
-        Ok(())
+    /// Maps DIO0 to `TxDone` and starts sending the payload, returning how long to wait for it
+    /// and the mapping DIO0 had before.
+    fn start_transmit_with_dio0(
+        &mut self,
+        payload: &[u8],
+        timeout_ms: Option<u32>,
+    ) -> Result<(u32, u8), LoRaError<E, CS, RESET, SW>> {
+        let timeout_ms = self.tx_timeout_ms(payload.len(), timeout_ms)?;
+        let mapping = self.get_dio_mapping(Dio::Dio0)?;
+        self.enable_interrupt(LoRaDio0::TxDone)?;
+        self.transmit_payload(payload)?;
+        Ok((timeout_ms, mapping))
     }
 
-    /// Sets the spreading factor of the radio. Supported values are between 6 and 12.
-    /// If a spreading factor of 6 is set, implicit header mode must be used to transmit
-    /// and receive packets. Default value is `7`.
-    pub fn set_spreading_factor(
+    /// Puts back a raw two-bit mapping returned by `get_dio_mapping()`.
+    fn restore_dio_mapping(
         &mut self,
-        sf: u8,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
-        let sf = sf.clamp(6, 12);
+        pin: Dio,
+        mapping: u8,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let (reg, offset) = pin.location();
+        let mut value = self.read_register(reg)?;
+        value.set_bits(offset..offset + 2, mapping);
+        self.write_register(reg, value)
+    }
 
-        if sf == 6 {
-            self.write_register(Register::DetectionOptimize, 0xc5)?;
-            self.write_register(Register::DetectionThreshold, 0x0c)?;
-        } else {
-            self.write_register(Register::DetectionOptimize, 0xc3)?;
-            self.write_register(Register::DetectionThreshold, 0x0a)?;
+    /// Clears `TxDone` if DIO0, mapped to it, is high, returning whether it was.
+    fn take_tx_done(&mut self, dio0_high: bool) -> Result<bool, LoRaError<E, CS, RESET, SW>> {
+        if dio0_high {
+            self.write_register(Register::IrqFlags, IRQMask::TxDone.addr())?;
         }
-        let modem_config_2 = self.read_register(Register::ModemConfig2)?;
-        self.write_register(
-            Register::ModemConfig2,
-            (modem_config_2 & 0x0f) | ((sf << 4) & 0xf0),
-        )?;
-        self.set_ldo_flag()?;
-        Ok(())
+        Ok(dio0_high)
     }
 
-    /// Sets the signal bandwidth of the radio. Supported values are: `7800 Hz`, `10400 Hz`,
-    /// `15600 Hz`, `20800 Hz`, `31250 Hz`,`41700 Hz` ,`62500 Hz`,`125000 Hz` and `250000 Hz`
-    /// Default value is `125000 Hz`
-    pub fn set_signal_bandwidth(
+    /// Returns `timeout_ms`, or the time on air of `len` bytes with room for the radio to ramp up.
+    fn tx_timeout_ms(
+        &mut self,
+        len: usize,
+        timeout_ms: Option<u32>,
+    ) -> Result<u32, LoRaError<E, CS, RESET, SW>> {
+        match timeout_ms {
+            Some(timeout_ms) => Ok(timeout_ms),
+            None => Ok(self.time_on_air_us(len)? / 1000 * 5 / 4 + 10),
+        }
+    }
+
+    fn wait_tx_done<DELAY: DelayMs<u16>, DIO>(
+        &mut self,
+        timeout_ms: u32,
+        delay: &mut DELAY,
+        mut done: impl FnMut(&mut Self) -> Result<bool, LoRaError<E, CS, RESET, SW, DIO>>,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW, DIO>> {
+        let mut count = 0;
+        loop {
+            if done(self)? {
+                return self.tx_finished().map_err(Error::with_dio);
+            }
+            if count >= timeout_ms {
+                self.set_mode(RadioMode::Stdby).map_err(Error::with_dio)?;
+                return Err(TxTimeout);
+            }
+            count += 1;
+            delay.delay_ms(1);
+        }
+    }
+
+    /// Maps DIO0 to `TxDone`, leaving the other pins' mappings untouched.
+    pub fn set_dio0_tx_done(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.set_dio_mapping(LoRaDio0::TxDone)
+    }
+
+    /// Routes a function to a DIO pin. Only the bits of the pin being mapped are changed, so
+    /// the other pins keep their current mappings. Use the `LoRaDio*` mappings in LoRa mode and
+    /// the `FskDio*` mappings in FSK/OOK mode.
+    pub fn set_dio_mapping<M: DioMapping>(
+        &mut self,
+        mapping: M,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let (reg, offset) = M::PIN.location();
+        let mut value = self.read_register(reg)?;
+        value.set_bits(offset..offset + 2, mapping.bits());
+        if let Some(map_preamble_detect) = mapping.map_preamble_detect() {
+            value.set_bit(0, map_preamble_detect);
+        }
+        self.write_register(reg, value)?;
+
+        Ok(())
+    }
+
+    /// Returns the raw two-bit mapping currently selected for a DIO pin.
+    pub fn get_dio_mapping(&mut self, pin: Dio) -> Result<u8, LoRaError<E, CS, RESET, SW>> {
+        let (reg, offset) = pin.location();
+        Ok(self.read_register(reg)?.get_bits(offset..offset + 2))
+    }
+
+    /// Sets the transmit power in dBm and the power amplifier used. Levels range from -4 to
+    /// +15 dBm on `PaSelect::Rfo` and from +2 to +20 dBm on `PaSelect::PaBoost`, levels
+    /// above +17 dBm switching on the high power PA_DAC mode. Levels outside the range of the
+    /// amplifier are clamped, and the level actually programmed is returned. On RFO, -4 dBm is
+    /// reached as -4.2 dBm. Check which pin your board wires to the antenna.
+    /// Default value is `17` on `PaSelect::PaBoost`.
+    pub fn set_tx_power(
+        &mut self,
+        level: i8,
+        pa: PaSelect,
+    ) -> Result<i8, LoRaError<E, CS, RESET, SW>> {
+        let mut pa_config = pa.addr();
+        match pa {
+            PaSelect::Rfo => {
+                let level = level.clamp(-4, 15);
+
+                // Pout = Pmax - (15 - OutputPower), with Pmax = 10.8 + 0.6 * MaxPower
+                let (max_power, output_power) = match level {
+                    0.. => (7, level),         // Pmax = 15 dBm
+                    -3..=-1 => (2, level + 3), // Pmax = 12 dBm
+                    _ => (0, 0),               // Pmax = 10.8 dBm
+                };
+                pa_config
+                    .set_bits(4..7, max_power)
+                    .set_bits(0..4, output_power as u8);
+
+                self.write_register(Register::PaDac, 0x84)?;
+                self.write_register(Register::PaConfig, pa_config)?;
+                Ok(level)
+            }
+            PaSelect::PaBoost => {
+                let level = level.clamp(2, 20);
+
+                let output_power = if level > 17 {
+                    // High Power +20 dBm Operation (Semtech SX1276/77/78/79 5.4.3.)
+                    // Pout = 5 + OutputPower
+                    self.write_register(Register::PaDac, 0x87)?;
+                    self.set_ocp(140)?;
+                    level - 5
+                } else {
+                    // Pout = 2 + OutputPower
+                    self.write_register(Register::PaDac, 0x84)?;
+                    self.set_ocp(100)?;
+                    level - 2
+                };
+                pa_config
+                    .set_bits(4..7, 7)
+                    .set_bits(0..4, output_power as u8);
+
+                self.write_register(Register::PaConfig, pa_config)?;
+                Ok(level)
+            }
+        }
+    }
+
+    /// Sets the over current protection on the radio(mA). The trim covers 45 to 120 mA in 5 mA
+    /// steps and 130 to 240 mA in 10 mA steps; other values are rejected with `InvalidOcp`.
+    /// Returns the current limit actually programmed, rounded down to the nearest step.
+    pub fn set_ocp(&mut self, ma: u8) -> Result<u8, LoRaError<E, CS, RESET, SW>> {
+        let (ocp_trim, limit) = match ma {
+            45..=120 => {
+                let trim = (ma - 45) / 5;
+                (trim, 45 + 5 * trim)
+            }
+            121..=240 => {
+                let trim = (ma / 10) + 3;
+                (trim, ma / 10 * 10)
+            }
+            _ => return Err(InvalidOcp(ma)),
+        };
+        self.write_register(Register::Ocp, 0x20 | (0x1F & ocp_trim))?;
+
+        Ok(limit)
+    }
+
+    /// Sets the rise and fall time of the power amplifier ramp in LoRa mode. Default value is
+    /// `PaRamp::_40us`.
+    pub fn set_pa_ramp(&mut self, ramp: PaRamp) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mut pa_ramp = self.read_register(Register::PaRamp)?;
+        pa_ramp.set_bits(0..4, ramp as u8);
+        self.write_register(Register::PaRamp, pa_ramp)?;
+
+        Ok(())
+    }
+
+    /// Sets the state of the radio. Default mode after initiation is `Standby`.
+    pub fn set_mode(&mut self, mode: RadioMode) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let antenna = match mode {
+            RadioMode::FsTx | RadioMode::Tx => AntennaMode::Tx,
+            RadioMode::RxContinuous | RadioMode::RxSingle | RadioMode::Cad => AntennaMode::Rx,
+            _ => AntennaMode::Off,
+        };
+        // connect the antenna before the radio uses it, disconnect it afterwards
+        if antenna != AntennaMode::Off {
+            self.set_antenna(antenna)?;
+        }
+        self.write_register(
+            Register::OpMode,
+            RadioMode::LongRangeMode.addr() | mode.addr(),
+        )?;
+        if self.fsk_mode {
+            // values read in FSK mode belong to the FSK registers
+            self.fsk_mode = false;
+            self.cache = RegisterCache::new();
+        }
+        if antenna == AntennaMode::Off {
+            self.set_antenna(antenna)?;
+        }
+
+        self.mode = mode;
+        Ok(())
+    }
+
+    fn set_antenna(&mut self, mode: AntennaMode) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.antenna_switch.set_antenna(mode).map_err(AntennaSwitch)
+    }
+
+    /// Records the return to standby the radio makes on its own once a packet is sent.
+    fn tx_finished(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.mode = RadioMode::Stdby;
+        self.set_antenna(AntennaMode::Off)
+    }
+
+    /// Sets the frequency of the radio. Values are in megahertz.
+    /// I.E. 915 MHz must be used for North America. Check regulation for your area.
+    pub fn set_frequency(&mut self, freq: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.tune(freq * 1_000_000)
+    }
+
+    /// Sets the carrier frequency in Hz, keeping the AFC correction.
+    fn tune(&mut self, freq_hz: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        if self.frequency_hz != 0 {
+            // the oscillator error is proportional to the carrier, carry it over to the new channel
+            self.afc_offset = self.afc_offset * freq_hz / self.frequency_hz;
+        }
+        self.frequency_hz = freq_hz;
+        self.write_frf(freq_hz + self.afc_offset)
+    }
+
+    /// Programs the synthesizer to the frequency in Hz.
+    fn write_frf(&mut self, freq_hz: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        // calculate register values
+        let frf = (freq_hz << 19) / i64::from(self.oscillator.frequency());
+        // write registers
+        self.write_register(Register::FrfMsb, ((frf & 0x00FF_0000) >> 16) as u8)?;
+        self.write_register(Register::FrfMid, ((frf & 0x0000_FF00) >> 8) as u8)?;
+        self.write_register(Register::FrfLsb, (frf & 0x0000_00FF) as u8)?;
+
+        Ok(())
+    }
+
+    /// Turns automatic frequency correction on or off. While on, the frequency error of every
+    /// packet received with a valid CRC is low-pass filtered into a correction of the carrier
+    /// frequency and of the data rate (`RegPpmCorrection`), so the radio follows the oscillator of
+    /// its peer. Turning it off returns to the nominal frequency. Default value is `false`.
+    pub fn set_afc(&mut self, enabled: bool) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.afc_enabled = enabled;
+        if !enabled && self.afc_offset != 0 {
+            self.apply_afc_offset(0)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the correction currently applied by automatic frequency correction, in Hz.
+    pub fn afc_offset_hz(&self) -> i64 {
+        self.afc_offset
+    }
+
+    /// Returns the correction currently applied by automatic frequency correction, in ppm of the
+    /// carrier frequency.
+    pub fn afc_offset_ppm(&self) -> f64 {
+        self.afc_offset as f64 * 1e6 / self.frequency_hz as f64
+    }
+
+    /// Folds the frequency error of the last received packet into the AFC correction.
+    fn track_frequency_error(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let error = self.get_packet_frequency_error()?;
+        // the demodulator tolerates an offset of a quarter of the bandwidth, anything beyond is noise
+        let limit = self.get_signal_bandwidth()?.max(0) / 4;
+        let offset = (self.afc_offset + error / 2).clamp(-limit, limit);
+
+        if offset != self.afc_offset {
+            self.apply_afc_offset(offset)?;
+        }
+
+        Ok(())
+    }
+
+    /// Retunes the carrier and data rate to a new AFC correction, restarting reception if needed.
+    fn apply_afc_offset(&mut self, offset: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mode = self.mode;
+        let receiving = matches!(mode, RadioMode::RxContinuous | RadioMode::RxSingle);
+        if receiving {
+            self.set_mode(RadioMode::Stdby)?;
+        }
+
+        self.afc_offset = offset;
+        self.write_frf(self.frequency_hz + offset)?;
+        // no carrier to correct relative to before a frequency is set
+        if self.frequency_hz != 0 {
+            // Semtech recommends a data rate correction of 95% of the frequency error, in ppm
+            let ppm_correction = (offset * 950_000 / self.frequency_hz).clamp(-128, 127);
+            self.write_register(Register::PpmCorrection, ppm_correction as i8 as u8)?;
+        }
+
+        if receiving {
+            self.set_mode(mode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the header mode used to transmit and receive packets. Default value is
+    /// `HeaderMode::Explicit`. `HeaderMode::Implicit` programs the fixed payload length, coding
+    /// rate and CRC setting on this radio, and must be configured identically on its peers.
+    pub fn set_header_mode(
         &mut self,
-        sbw: i64,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+        header_mode: HeaderMode,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mut modem_config_1 = self.read_register(Register::ModemConfig1)?;
+        match header_mode {
+            HeaderMode::Explicit => {
+                modem_config_1.set_bit(0, false);
+            }
+            HeaderMode::Implicit { len, cr, crc } => {
+                modem_config_1
+                    .set_bit(0, true)
+                    .set_bits(1..4, cr.clamp(5, 8) - 4);
+                self.write_register(Register::PayloadLength, len)?;
+                self.set_crc(crc)?;
+            }
+        }
+        self.write_register(Register::ModemConfig1, modem_config_1)?;
+
+        self.header_mode = match header_mode {
+            HeaderMode::Implicit { len, cr, crc } => HeaderMode::Implicit {
+                len,
+                cr: cr.clamp(5, 8),
+                crc,
+            },
+            HeaderMode::Explicit => HeaderMode::Explicit,
+        };
+        Ok(())
+    }
+
+    /// Returns the header mode used to transmit and receive packets.
+    pub fn header_mode(&self) -> HeaderMode {
+        self.header_mode
+    }
+
+    /// Sets the spreading factor of the radio. Supported values are between 6 and 12.
+    /// If a spreading factor of 6 is set, `HeaderMode::Implicit` must be used to transmit
+    /// and receive packets. Default value is `7`.
+    pub fn set_spreading_factor(&mut self, sf: u8) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let sf = sf.clamp(6, 12);
+
+        if sf == 6 {
+            self.write_register(Register::DetectionOptimize, 0xc5)?;
+            self.write_register(Register::DetectionThreshold, 0x0c)?;
+        } else {
+            self.write_register(Register::DetectionOptimize, 0xc3)?;
+            self.write_register(Register::DetectionThreshold, 0x0a)?;
+        }
+        let modem_config_2 = self.read_register(Register::ModemConfig2)?;
+        self.write_register(
+            Register::ModemConfig2,
+            (modem_config_2 & 0x0f) | ((sf << 4) & 0xf0),
+        )?;
+        self.set_ldo_flag()?;
+        Ok(())
+    }
+
+    /// Sets the signal bandwidth of the radio. Supported values are: `7800 Hz`, `10400 Hz`,
+    /// `15600 Hz`, `20800 Hz`, `31250 Hz`,`41700 Hz` ,`62500 Hz`,`125000 Hz` and `250000 Hz`
+    /// Default value is `125000 Hz`
+    pub fn set_signal_bandwidth(&mut self, sbw: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let bw: i64 = match sbw {
             7_800 => 0,
             10_400 => 1,
@@ -412,22 +1222,22 @@ where
     pub fn set_coding_rate_4(
         &mut self,
         denominator: u8,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let denominator = denominator.clamp(5, 8);
 
         let cr = denominator - 4;
         let modem_config_1 = self.read_register(Register::ModemConfig1)?;
         self.write_register(Register::ModemConfig1, (modem_config_1 & 0xf1) | (cr << 1))?;
+        if let HeaderMode::Implicit { cr, .. } = &mut self.header_mode {
+            *cr = denominator;
+        }
 
         Ok(())
     }
 
     /// Sets the preamble length of the radio. Values are between 6 and 65535.
     /// Default value is `8`.
-    pub fn set_preamble_length(
-        &mut self,
-        length: i64,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    pub fn set_preamble_length(&mut self, length: i64) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         self.write_register(Register::PreambleMsb, (length >> 8) as u8)?;
         self.write_register(Register::PreambleLsb, length as u8)?;
 
@@ -435,7 +1245,7 @@ where
     }
 
     /// Enables are disables the radio's CRC check. Default value is `false`.
-    pub fn set_crc(&mut self, value: bool) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    pub fn set_crc(&mut self, value: bool) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let modem_config_2 = self.read_register(Register::ModemConfig2)?;
         if value {
             self.write_register(Register::ModemConfig2, modem_config_2 | 0x04)?;
@@ -443,11 +1253,15 @@ where
             self.write_register(Register::ModemConfig2, modem_config_2 & 0xfb)?;
         }
 
+        if let HeaderMode::Implicit { crc, .. } = &mut self.header_mode {
+            *crc = value;
+        }
+
         Ok(())
     }
 
     /// Inverts the radio's IQ signals. Default value is `false`.
-    pub fn set_invert_iq(&mut self, value: bool) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    pub fn set_invert_iq(&mut self, value: bool) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         if value {
             self.write_register(Register::Invertiq, 0x66)?;
             self.write_register(Register::Invertiq2, 0x19)?;
@@ -458,13 +1272,41 @@ where
         Ok(())
     }
 
+    /// Sets the gain of the receiver's low noise amplifier, and whether the LNA current boost of
+    /// the high frequency port is on. `LnaGain::Agc` hands the gain over to the automatic gain
+    /// control loop; a manual gain step turns the loop off. Applies to the modem the radio is
+    /// currently in, LoRa or FSK/OOK. Default value is `LnaGain::Agc` with boost on.
+    pub fn set_lna(
+        &mut self,
+        gain: LnaGain,
+        boost: bool,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let mut lna = self.read_register(Register::Lna)?;
+        lna.set_bits(5..8, gain.bits())
+            .set_bits(0..2, if boost { 0b11 } else { 0b00 });
+        self.write_register(Register::Lna, lna)?;
+
+        let agc = gain == LnaGain::Agc;
+        if self.read_register(Register::OpMode)? & RadioMode::LongRangeMode.addr() != 0 {
+            let mut config_3 = self.read_register(Register::ModemConfig3)?;
+            config_3.set_bit(2, agc);
+            self.write_register(Register::ModemConfig3, config_3)?;
+        } else {
+            let mut rx_config = self.read_register(FskRegister::RxConfig)?;
+            rx_config.set_bit(3, agc);
+            self.write_register(FskRegister::RxConfig, rx_config)?;
+        }
+
+        Ok(())
+    }
+
     /// Returns the spreading factor of the radio.
-    pub fn get_spreading_factor(&mut self) -> Result<u8, Error<E, CS::Error, RESET::Error>> {
+    pub fn get_spreading_factor(&mut self) -> Result<u8, LoRaError<E, CS, RESET, SW>> {
         Ok(self.read_register(Register::ModemConfig2)? >> 4)
     }
 
     /// Returns the signal bandwidth of the radio.
-    pub fn get_signal_bandwidth(&mut self) -> Result<i64, Error<E, CS::Error, RESET::Error>> {
+    pub fn get_signal_bandwidth(&mut self) -> Result<i64, LoRaError<E, CS, RESET, SW>> {
         let bw = self.read_register(Register::ModemConfig1)? >> 4;
         let bw = match bw {
             0 => 7_800,
@@ -483,31 +1325,36 @@ where
     }
 
     /// Returns the RSSI of the last received packet.
-    pub fn get_packet_rssi(&mut self) -> Result<i32, Error<E, CS::Error, RESET::Error>> {
+    pub fn get_packet_rssi(&mut self) -> Result<i32, LoRaError<E, CS, RESET, SW>> {
         Ok(i32::from(self.read_register(Register::PktRssiValue)?) - 157)
     }
 
     /// Returns the signal to noise radio of the the last received packet.
-    pub fn get_packet_snr(&mut self) -> Result<f64, Error<E, CS::Error, RESET::Error>> {
+    pub fn get_packet_snr(&mut self) -> Result<f64, LoRaError<E, CS, RESET, SW>> {
         Ok(f64::from(self.read_register(Register::PktSnrValue)?))
     }
 
     /// Returns the frequency error of the last received packet in Hz.
-    pub fn get_packet_frequency_error(&mut self) -> Result<i64, Error<E, CS::Error, RESET::Error>> {
+    pub fn get_packet_frequency_error(&mut self) -> Result<i64, LoRaError<E, CS, RESET, SW>> {
         let mut freq_error: i32;
-        freq_error = i32::from(self.read_register(Register::FreqErrorMsb)? & 0x7);
+        freq_error = i32::from(self.read_register(Register::FreqErrorMsb)? & 0xf);
         freq_error <<= 8_i64;
         freq_error += i32::from(self.read_register(Register::FreqErrorMid)?);
         freq_error <<= 8_i64;
         freq_error += i32::from(self.read_register(Register::FreqErrorLsb)?);
 
-        let f_xtal = 32_000_000; // FXOSC: crystal oscillator (XTAL) frequency (2.5. Chip Specification, p. 14)
+        // the error is a 20 bit two's complement value
+        if freq_error.get_bit(19) {
+            freq_error -= 1 << 20;
+        }
+
+        let f_xtal = self.oscillator.frequency(); // FXOSC: crystal oscillator (XTAL) frequency (2.5. Chip Specification, p. 14)
         let f_error = ((f64::from(freq_error) * (1i64 << 24) as f64) / f64::from(f_xtal))
             * (self.get_signal_bandwidth()? as f64 / 500_000.0f64); // p. 37
         Ok(f_error as i64)
     }
 
-    fn set_ldo_flag(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    fn set_ldo_flag(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let sw = self.get_signal_bandwidth()?;
         // Section 4.1.1.5
         let symbol_duration = 1000 / (sw / ((1_i64) << self.get_spreading_factor()?));
@@ -522,7 +1369,41 @@ where
         Ok(())
     }
 
-    fn read_register(&mut self, reg: Register) -> Result<u8, Error<E, CS::Error, RESET::Error>> {
+    /// Keeps a shadow copy of the configuration registers, so that settings are updated
+    /// without reading the registers back and only registers whose value changes are written.
+    /// Disabled by default. Nothing but the driver may write to the radio while it is enabled,
+    /// see `sync_from_chip` otherwise.
+    pub fn set_shadow_registers(&mut self, enabled: bool) {
+        self.shadow_registers = enabled;
+    }
+
+    /// Reloads the shadow copy of the configuration registers from the radio.
+    pub fn sync_from_chip(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        for reg in RegisterCache::registers() {
+            let value = self.read_chip_register(reg)?;
+            self.cache.set(reg.addr(), value);
+            if !self.fsk_mode {
+                self.snapshot.set(reg.addr(), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_register<R: AsAddr>(&mut self, reg: R) -> Result<u8, LoRaError<E, CS, RESET, SW>> {
+        let reg = reg.addr();
+        if !self.shadow_registers {
+            return self.read_chip_register(reg);
+        }
+        if let Some(value) = self.cache.get(reg) {
+            return Ok(value);
+        }
+        let value = self.read_chip_register(reg)?;
+        self.cache.set(reg, value);
+        Ok(value)
+    }
+
+    /// Reads a register over SPI, bypassing the shadow registers.
+    fn read_chip_register<R: AsAddr>(&mut self, reg: R) -> Result<u8, LoRaError<E, CS, RESET, SW>> {
         let reg = reg.addr();
         self.cs.set_low().map_err(CS)?;
 
@@ -532,22 +1413,163 @@ where
         Ok(transfer[1])
     }
 
-    fn write_register(
+    /// Reads consecutive bytes from the FIFO in a single SPI burst.
+    fn read_fifo(&mut self, buffer: &mut [u8]) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        self.cs.set_low().map_err(CS)?;
+
+        self.spi
+            .write(&[Register::Fifo.addr() & 0x7f])
+            .map_err(Spi)?;
+        self.spi.transfer(buffer).map_err(Spi)?;
+        self.cs.set_high().map_err(CS)?;
+        Ok(())
+    }
+
+    /// Writes the slices into the FIFO one after another, in a single SPI burst.
+    fn write_fifo(&mut self, payloads: &[&[u8]]) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.cs.set_low().map_err(CS)?;
+
+        self.spi
+            .write(&[Register::Fifo.addr() | 0x80])
+            .map_err(Spi)?;
+        for payload in payloads {
+            self.spi.write(payload).map_err(Spi)?;
+        }
+        self.cs.set_high().map_err(CS)?;
+        Ok(())
+    }
+
+    fn write_register<R: AsAddr>(
         &mut self,
-        reg: Register,
+        reg: R,
         byte: u8,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let reg = reg.addr();
+        if self.shadow_registers && self.cache.get(reg) == Some(byte) {
+            return Ok(());
+        }
+        self.write_chip_register(reg, byte)
+    }
+
+    /// Writes a register over SPI even if the shadow registers hold the same value.
+    fn write_chip_register<R: AsAddr>(
+        &mut self,
+        reg: R,
+        byte: u8,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let reg = reg.addr();
         self.cs.set_low().map_err(CS)?;
 
         let buffer = [reg | 0x80, byte];
         self.spi.write(&buffer).map_err(Spi)?;
         self.cs.set_high().map_err(CS)?;
+        self.cache.set(reg, byte);
+        if !self.fsk_mode {
+            self.snapshot.set(reg, byte);
+        }
         Ok(())
     }
 
+    /// Checks that the radio is still responding and configured as the driver left it, and
+    /// recovers it otherwise.
+    ///
+    /// A brown-out or a glitch on the reset line puts the radio back in its power-on state, and a
+    /// disturbed SPI bus can leave it in an unexpected mode. Either is detected from
+    /// `Register::Version`, the operating mode and the key LoRa configuration registers; a fault
+    /// seen twice in a row triggers `recover`, and is returned so it can be logged. Packets being
+    /// sent or received at the time are lost. Only meaningful while the radio is used in LoRa mode.
+    pub fn check_health<DELAY: DelayMs<u16>>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<Health, LoRaError<E, CS, RESET, SW>> {
+        if self.detect_fault()?.is_none() {
+            return Ok(Health::Ok);
+        }
+        // a single corrupted read is not worth a reset
+        match self.detect_fault()? {
+            Some(fault) => {
+                self.recover(delay)?;
+                Ok(Health::Recovered(fault))
+            }
+            None => Ok(Health::Ok),
+        }
+    }
+
+    /// Resets the radio and writes back every configuration register set through the driver,
+    /// then returns to the mode it was in. A transmission in progress is not restarted, the radio
+    /// is left in standby instead.
+    pub fn recover<DELAY: DelayMs<u16>>(
+        &mut self,
+        delay: &mut DELAY,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        self.reset.set_low().map_err(Reset)?;
+        delay.delay_ms(10);
+        self.reset.set_high().map_err(Reset)?;
+        delay.delay_ms(10);
+        let version = self.read_register(Register::Version)?;
+        if version != VERSION_CHECK {
+            return Err(Error::VersionMismatch(version));
+        }
+        let mode = match self.mode {
+            RadioMode::Tx | RadioMode::FsTx | RadioMode::Cad => RadioMode::Stdby,
+            mode => mode,
+        };
+        self.set_mode(RadioMode::Sleep)?;
+        let snapshot = self.snapshot.clone();
+        for (reg, value) in snapshot.iter() {
+            self.write_chip_register(reg, value)?;
+        }
+        self.pending_rx_flags = 0;
+        self.rx_done_us = None;
+        self.set_mode(mode)
+    }
+
+    /// Returns the first sign that the radio lost its state, if any.
+    fn detect_fault(&mut self) -> Result<Option<Fault>, LoRaError<E, CS, RESET, SW>> {
+        let version = self.read_chip_register(Register::Version)?;
+        if version != VERSION_CHECK {
+            return Ok(Some(Fault::VersionMismatch(version)));
+        }
+        let op_mode = self.read_chip_register(Register::OpMode)?;
+        let mode = op_mode.get_bits(0..3);
+        // single transmit and receive return to standby on their own
+        let expected = match self.mode {
+            RadioMode::Tx | RadioMode::RxSingle | RadioMode::Cad => {
+                mode == self.mode.addr() || mode == RadioMode::Stdby.addr()
+            }
+            _ => mode == self.mode.addr(),
+        };
+        if !op_mode.get_bit(7) || !expected {
+            return Ok(Some(Fault::UnexpectedMode(op_mode)));
+        }
+        for reg in [
+            Register::FrfMsb,
+            Register::FrfMid,
+            Register::FrfLsb,
+            Register::ModemConfig1,
+            Register::ModemConfig2,
+            Register::ModemConfig3,
+            Register::SyncWord,
+        ] {
+            if let Some(expected) = self.snapshot.get(reg.addr()) {
+                let actual = self.read_chip_register(reg)?;
+                if actual != expected {
+                    return Ok(Some(Fault::ConfigLost {
+                        register: reg.addr(),
+                        expected,
+                        actual,
+                    }));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     /// Puts the radio in FSK mode.
-    pub fn put_in_fsk_mode(&mut self) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+    pub fn put_in_fsk_mode(&mut self) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         // Put in FSK mode
         let mut op_mode: u8 = 0x0;
         op_mode
@@ -557,6 +1579,10 @@ where
             .set_bits(0..2, 0b011); // Mode
 
         self.write_register(Register::OpMode, op_mode)?;
+        // the LoRa configuration registers have a different meaning in FSK mode; the snapshot
+        // kept for `recover` is left alone
+        self.fsk_mode = true;
+        self.cache = RegisterCache::new();
 
         Ok(())
     }
@@ -565,17 +1591,174 @@ where
     pub fn set_fsk_pa_ramp(
         &mut self,
         modulation_shaping: FskDataModulationShaping,
-        ramp: FskRampUpRamDown,
-    ) -> Result<(), Error<E, CS::Error, RESET::Error>> {
+        ramp: PaRamp,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
         let mut pa_ramp: u8 = 0x0;
         pa_ramp
-            .set_bits(5..6, modulation_shaping as u8)
-            .set_bits(0..3, ramp as u8);
+            .set_bits(5..7, modulation_shaping as u8)
+            .set_bits(0..4, ramp as u8);
 
         self.write_register(Register::PaRamp, pa_ramp)?;
 
         Ok(())
     }
+
+    /// Sets the bit rate for FSK mode, in bits per second.
+    pub fn set_fsk_bitrate(&mut self, bitrate: u32) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let bitrate = (self.oscillator.frequency() / bitrate.max(1)).min(0xffff);
+
+        self.write_register(FskRegister::BitrateMsb, (bitrate >> 8) as u8)?;
+        self.write_register(FskRegister::BitrateLsb, bitrate as u8)?;
+
+        Ok(())
+    }
+
+    /// Sets the frequency deviation for FSK mode, in Hz.
+    pub fn set_fsk_frequency_deviation(
+        &mut self,
+        deviation: u32,
+    ) -> Result<(), LoRaError<E, CS, RESET, SW>> {
+        let fdev =
+            ((u64::from(deviation) << 19) / u64::from(self.oscillator.frequency())).min(0x3fff);
+
+        self.write_register(FskRegister::FdevMsb, (fdev >> 8) as u8)?;
+        self.write_register(FskRegister::FdevLsb, fdev as u8)?;
+
+        Ok(())
+    }
+}
+
+/// Settings applied when the radio is built with `LoRa::with_config`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// Carrier frequency in megahertz.
+    pub frequency: i64,
+    /// Reference oscillator of the board.
+    pub oscillator: Oscillator,
+    /// Power amplifier output wired to the antenna, used by `ConfigurableRadio::set_tx_power_dbm`.
+    pub pa_select: PaSelect,
+}
+
+impl Config {
+    /// Returns the configuration for a board with the reference 32 MHz crystal and the antenna on
+    /// PA_BOOST, as on the RFM95/96/98 modules.
+    pub fn new(frequency: i64) -> Self {
+        Config {
+            frequency,
+            oscillator: Oscillator::default(),
+            pa_select: PaSelect::PaBoost,
+        }
+    }
+}
+
+/// Reference oscillator of the radio, which every frequency, bit rate, deviation and frequency
+/// error is derived from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Oscillator {
+    /// A crystal across XTA and XTB, with its frequency in Hz.
+    Crystal(u32),
+    /// A TCXO driving XTA, with its frequency in Hz. Enables `TcxoInputOn` in `RegTcxo`.
+    Tcxo(u32),
+}
+
+impl Oscillator {
+    /// Returns the oscillator frequency in Hz.
+    pub fn frequency(self) -> u32 {
+        match self {
+            Oscillator::Crystal(frequency) | Oscillator::Tcxo(frequency) => frequency,
+        }
+    }
+}
+
+impl Default for Oscillator {
+    /// The 32 MHz crystal of the reference design.
+    fn default() -> Self {
+        Oscillator::Crystal(32_000_000)
+    }
+}
+
+/// Snapshot of `RegModemStat`, returned by `LoRa::modem_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModemStatus {
+    flags: u8,
+}
+
+impl ModemStatus {
+    /// Returns the raw register value.
+    pub fn flags(self) -> u8 {
+        self.flags
+    }
+
+    /// A LoRa preamble has been detected.
+    pub fn signal_detected(self) -> bool {
+        self.flags.get_bit(0)
+    }
+
+    /// The modem has synchronised to the preamble.
+    pub fn signal_synchronized(self) -> bool {
+        self.flags.get_bit(1)
+    }
+
+    /// A packet is being received.
+    pub fn rx_ongoing(self) -> bool {
+        self.flags.get_bit(2)
+    }
+
+    /// A valid header has been received and the payload is coming in.
+    pub fn header_valid(self) -> bool {
+        self.flags.get_bit(3)
+    }
+
+    /// The modem is idle.
+    pub fn modem_clear(self) -> bool {
+        self.flags.get_bit(4)
+    }
+
+    /// Coding rate denominator of the last header received, between `5` and `8`.
+    pub fn coding_rate(self) -> u8 {
+        self.flags.get_bits(5..8) + 4
+    }
+
+    /// Returns true if switching modes now would cut a packet off.
+    pub fn receiving(self) -> bool {
+        self.flags & 0b0000_1111 != 0
+    }
+}
+
+/// Result of `LoRa::check_health`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    /// The radio is responding and configured as expected.
+    Ok,
+    /// The radio had lost its state and was reset and reconfigured.
+    Recovered(Fault),
+}
+
+/// What made `LoRa::check_health` recover the radio.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    /// `Register::Version` read back this value instead of the expected one: the radio did not
+    /// respond or was held in reset.
+    VersionMismatch(u8),
+    /// The radio left LoRa mode or the mode the driver put it in, with the value of `RegOpMode`.
+    UnexpectedMode(u8),
+    /// A configuration register no longer held the value last written to it.
+    ConfigLost {
+        register: u8,
+        expected: u8,
+        actual: u8,
+    },
+}
+
+/// Header mode of LoRa packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderMode {
+    /// Every packet starts with a header carrying its length, coding rate and CRC presence.
+    Explicit,
+    /// No header is sent, so the payload length `len`, the coding rate denominator `cr`
+    /// (between `5` and `8`, for `4/5` to `4/8`) and whether a payload CRC is present are fixed
+    /// in advance. Required at spreading factor 6.
+    Implicit { len: u8, cr: u8, crc: bool },
 }
 
 /// Modes of the radio and their corresponding register values.
@@ -584,9 +1767,12 @@ pub enum RadioMode {
     LongRangeMode = 0x80,
     Sleep = 0x00,
     Stdby = 0x01,
+    FsTx = 0x02,
     Tx = 0x03,
     RxContinuous = 0x05,
     RxSingle = 0x06,
+    /// Channel activity detection, reported by `RadioEvent::CadDone`.
+    Cad = 0x07,
 }
 
 impl AsAddr for RadioMode {
@@ -595,3 +1781,1009 @@ impl AsAddr for RadioMode {
         self as u8
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use core::convert::Infallible;
+    use std::rc::Rc;
+
+    /// Register-level model of the radio, enough to exercise the driver over SPI.
+    struct Chip {
+        regs: [u8; 0x80],
+        fifo: [u8; 256],
+        frame: Option<u8>,
+        /// Number of SPI frames started so far.
+        frames: usize,
+    }
+
+    impl Chip {
+        fn read(&mut self, addr: u8) -> u8 {
+            if addr == Register::Fifo.addr() {
+                let ptr = self.regs[Register::FifoAddrPtr.addr() as usize];
+                self.regs[Register::FifoAddrPtr.addr() as usize] = ptr.wrapping_add(1);
+                self.fifo[ptr as usize]
+            } else {
+                self.regs[addr as usize]
+            }
+        }
+
+        fn write(&mut self, addr: u8, byte: u8) {
+            if addr == Register::Fifo.addr() {
+                let ptr = self.regs[Register::FifoAddrPtr.addr() as usize];
+                self.regs[Register::FifoAddrPtr.addr() as usize] = ptr.wrapping_add(1);
+                self.fifo[ptr as usize] = byte;
+            } else if addr == Register::IrqFlags.addr() {
+                self.regs[addr as usize] &= !byte;
+            } else {
+                self.regs[addr as usize] = byte;
+            }
+        }
+
+        /// Clocks one byte through the current frame, returning the byte shifted out.
+        fn clock(&mut self, byte: u8) -> u8 {
+            match self.frame {
+                None => {
+                    self.frame = Some(byte);
+                    0
+                }
+                Some(header) => {
+                    let addr = header & 0x7f;
+                    let out = if header & 0x80 != 0 {
+                        self.write(addr, byte);
+                        0
+                    } else {
+                        self.read(addr)
+                    };
+                    if addr != Register::Fifo.addr() {
+                        self.frame = Some(header.wrapping_add(1));
+                    }
+                    out
+                }
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    struct FakeChip(Rc<RefCell<Chip>>);
+
+    impl FakeChip {
+        fn new() -> Self {
+            let mut regs = [0; 0x80];
+            regs[Register::Version.addr() as usize] = VERSION_CHECK;
+            FakeChip(Rc::new(RefCell::new(Chip {
+                regs,
+                fifo: [0; 256],
+                frame: None,
+                frames: 0,
+            })))
+        }
+
+        fn reg(&self, reg: Register) -> u8 {
+            self.0.borrow().regs[reg.addr() as usize]
+        }
+
+        fn set_reg(&self, reg: Register, byte: u8) {
+            self.0.borrow_mut().regs[reg.addr() as usize] = byte;
+        }
+    }
+
+    impl Transfer<u8> for FakeChip {
+        type Error = Infallible;
+
+        fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Infallible> {
+            let mut chip = self.0.borrow_mut();
+            for word in words.iter_mut() {
+                *word = chip.clock(*word);
+            }
+            Ok(words)
+        }
+    }
+
+    impl Write<u8> for FakeChip {
+        type Error = Infallible;
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+            let mut chip = self.0.borrow_mut();
+            for &word in words {
+                chip.clock(word);
+            }
+            Ok(())
+        }
+    }
+
+    /// Chip select line; pulling it low starts a new SPI frame.
+    struct FakeCs(Rc<RefCell<Chip>>);
+
+    impl OutputPin for FakeCs {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            let mut chip = self.0.borrow_mut();
+            chip.frame = None;
+            chip.frames += 1;
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct FakeReset;
+
+    impl OutputPin for FakeReset {
+        type Error = Infallible;
+
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayMs<u16> for NoDelay {
+        fn delay_ms(&mut self, _ms: u16) {}
+    }
+
+    impl DelayMs<u8> for NoDelay {
+        fn delay_ms(&mut self, _ms: u8) {}
+    }
+
+    fn radio() -> (FakeChip, LoRa<FakeChip, FakeCs, FakeReset>) {
+        radio_with(Config::new(915))
+    }
+
+    fn radio_with(config: Config) -> (FakeChip, LoRa<FakeChip, FakeCs, FakeReset>) {
+        let chip = FakeChip::new();
+        let cs = FakeCs(chip.0.clone());
+        let lora = LoRa::with_config(chip.clone(), cs, FakeReset, config, &mut NoDelay).unwrap();
+        (chip, lora)
+    }
+
+    fn frf(chip: &FakeChip) -> u32 {
+        u32::from(chip.reg(Register::FrfMsb)) << 16
+            | u32::from(chip.reg(Register::FrfMid)) << 8
+            | u32::from(chip.reg(Register::FrfLsb))
+    }
+
+    #[test]
+    fn dio_mapping_preserves_other_pins() {
+        use dio::{FskDio4, LoRaDio1, LoRaDio3, LoRaDio5};
+
+        let (chip, mut lora) = radio();
+        lora.set_dio_mapping(LoRaDio1::CadDetected).unwrap();
+        lora.set_dio_mapping(LoRaDio3::ValidHeader).unwrap();
+        lora.set_dio0_tx_done().unwrap();
+        assert_eq!(chip.reg(Register::DioMapping1), 0b01_10_00_01);
+
+        lora.set_dio_mapping(LoRaDio5::ClkOut).unwrap();
+        lora.set_dio_mapping(FskDio4::PreambleDetect).unwrap();
+        assert_eq!(chip.reg(Register::DioMapping2), 0b1101_0001);
+        assert_eq!(lora.get_dio_mapping(Dio::Dio5).unwrap(), 0b01);
+    }
+
+    #[test]
+    fn irq_mask_follows_interrupt_routing() {
+        use dio::LoRaDio1;
+
+        let (chip, mut lora) = radio();
+        lora.set_enabled_irqs(&[IRQMask::RxDone]).unwrap();
+        assert_eq!(chip.reg(Register::IrqFlagsMask), !IRQMask::RxDone.addr());
+
+        lora.enable_interrupt(LoRaDio1::RxTimeout).unwrap();
+        assert!(lora.irq_enabled(IRQMask::RxTimeout).unwrap());
+        assert!(!lora.irq_enabled(IRQMask::ValidHeader).unwrap());
+        assert_eq!(lora.get_dio_mapping(Dio::Dio1).unwrap(), 0b00);
+
+        lora.set_irq_enabled(IRQMask::RxDone, false).unwrap();
+        assert!(!lora.irq_enabled(IRQMask::RxDone).unwrap());
+    }
+
+    #[test]
+    fn read_packet_into_reports_truncation() {
+        let (chip, mut lora) = radio();
+        chip.0.borrow_mut().fifo[..5].copy_from_slice(&[1, 2, 3, 4, 5]);
+        chip.set_reg(Register::RxNbBytes, 5);
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+
+        let mut buffer = [0; 3];
+        assert_eq!(lora.read_packet_into(&mut buffer).unwrap(), Some(5));
+        assert_eq!(buffer, [1, 2, 3]);
+        assert_eq!(lora.read_packet_into(&mut buffer).unwrap(), None);
+    }
+
+    #[test]
+    fn transmit_vectored_streams_slices() {
+        let (chip, mut lora) = radio();
+        lora.transmit_vectored(&[&[0xaa], &[1, 2], &[0x55]])
+            .unwrap();
+        assert_eq!(&chip.0.borrow().fifo[..4], &[0xaa, 1, 2, 0x55]);
+        assert_eq!(chip.reg(Register::PayloadLength), 4);
+
+        let body = [0; 250];
+        assert!(matches!(
+            lora.transmit_vectored(&[&[0; 4], &body, &[0; 4]]),
+            Err(PayloadTooLarge(258))
+        ));
+    }
+
+    #[test]
+    fn implicit_header_mode_configures_both_sides() {
+        let (chip, mut lora) = radio();
+        chip.set_reg(Register::ModemConfig1, 0x72);
+        lora.set_header_mode(HeaderMode::Implicit {
+            len: 4,
+            cr: 8,
+            crc: true,
+        })
+        .unwrap();
+        assert_eq!(chip.reg(Register::ModemConfig1), 0x79);
+        assert_eq!(chip.reg(Register::PayloadLength), 4);
+        assert!(chip.reg(Register::ModemConfig2).get_bit(2));
+
+        assert!(matches!(
+            lora.transmit_payload(&[1, 2, 3]),
+            Err(PayloadLengthMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        lora.transmit_payload(&[1, 2, 3, 4]).unwrap();
+
+        lora.set_header_mode(HeaderMode::Explicit).unwrap();
+        assert_eq!(chip.reg(Register::ModemConfig1), 0x78);
+    }
+
+    #[test]
+    fn lna_gain_selects_agc_per_modem() {
+        let (chip, mut lora) = radio();
+        assert_eq!(chip.reg(Register::Lna), 0b0010_0011);
+        assert!(chip.reg(Register::ModemConfig3).get_bit(2));
+
+        lora.set_lna(LnaGain::G6, false).unwrap();
+        assert_eq!(chip.reg(Register::Lna), 0b1100_0000);
+        assert!(!chip.reg(Register::ModemConfig3).get_bit(2));
+
+        lora.put_in_fsk_mode().unwrap();
+        lora.set_lna(LnaGain::Agc, true).unwrap();
+        assert!(chip.0.borrow().regs[FskRegister::RxConfig.addr() as usize].get_bit(3));
+    }
+
+    #[test]
+    fn tx_power_maps_exact_dbm() {
+        let (chip, mut lora) = radio();
+        assert_eq!(lora.set_tx_power(20, PaSelect::PaBoost).unwrap(), 20);
+        assert_eq!(chip.reg(Register::PaConfig), 0xff);
+        assert_eq!(chip.reg(Register::PaDac), 0x87);
+
+        assert_eq!(lora.set_tx_power(17, PaSelect::PaBoost).unwrap(), 17);
+        assert_eq!(chip.reg(Register::PaConfig), 0xff);
+        assert_eq!(chip.reg(Register::PaDac), 0x84);
+
+        assert_eq!(lora.set_tx_power(30, PaSelect::Rfo).unwrap(), 15);
+        assert_eq!(chip.reg(Register::PaConfig), 0x7f);
+        assert_eq!(lora.set_tx_power(-2, PaSelect::Rfo).unwrap(), -2);
+        assert_eq!(chip.reg(Register::PaConfig), 0x21);
+    }
+
+    #[test]
+    fn ocp_is_validated() {
+        let (chip, mut lora) = radio();
+        assert!(matches!(lora.set_ocp(30), Err(InvalidOcp(30))));
+        assert_eq!(lora.set_ocp(123).unwrap(), 120);
+        assert_eq!(chip.reg(Register::Ocp), 0x20 | 15);
+        assert_eq!(lora.set_ocp(240).unwrap(), 240);
+        assert_eq!(chip.reg(Register::Ocp), 0x20 | 27);
+    }
+
+    #[test]
+    fn oscillator_drives_frequency_calculations() {
+        let (chip, _) = radio();
+        assert_eq!(frf(&chip), 0xe4c000);
+        assert!(!chip.reg(Register::Tcxo).get_bit(4));
+
+        let (chip, mut lora) = radio_with(Config {
+            oscillator: Oscillator::Tcxo(30_000_000),
+            ..Config::new(868)
+        });
+        assert_eq!(frf(&chip), ((868_000_000u64 << 19) / 30_000_000) as u32);
+        assert!(chip.reg(Register::Tcxo).get_bit(4));
+
+        // -1000 in 20 bit two's complement, at 125 kHz bandwidth
+        chip.set_reg(Register::ModemConfig1, 0x72);
+        chip.set_reg(Register::FreqErrorMsb, 0x0f);
+        chip.set_reg(Register::FreqErrorMid, 0xfc);
+        chip.set_reg(Register::FreqErrorLsb, 0x18);
+        assert_eq!(lora.get_packet_frequency_error().unwrap(), -139);
+    }
+
+    #[test]
+    fn afc_tracks_packet_frequency_error() {
+        let (chip, mut lora) = radio();
+        let nominal = frf(&chip);
+        lora.set_afc(true).unwrap();
+
+        // +2000 Hz at 125 kHz bandwidth
+        chip.set_reg(Register::ModemConfig1, 0x72);
+        chip.set_reg(Register::FreqErrorMid, 0x3b);
+        chip.set_reg(Register::FreqErrorLsb, 0x9b);
+        for _ in 0..2 {
+            chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+            lora.read_packet().unwrap().unwrap();
+        }
+        assert_eq!(lora.afc_offset_hz(), 2000);
+        assert_eq!(frf(&chip), nominal + 32);
+        assert_eq!(chip.reg(Register::PpmCorrection), 2);
+        assert!((lora.afc_offset_ppm() - 2.186).abs() < 0.001);
+
+        chip.set_reg(
+            Register::IrqFlags,
+            IRQMask::RxDone.addr() | IRQMask::PayloadCrcError.addr(),
+        );
+        lora.read_packet().unwrap().unwrap();
+        assert_eq!(lora.afc_offset_hz(), 2000);
+
+        lora.set_afc(false).unwrap();
+        assert_eq!(frf(&chip), nominal);
+        assert_eq!(chip.reg(Register::PpmCorrection), 0);
+
+        // without a carrier frequency there is no data rate correction to derive
+        lora.set_frequency(0).unwrap();
+        lora.set_afc(true).unwrap();
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        lora.read_packet().unwrap().unwrap();
+        assert_eq!(lora.afc_offset_hz(), 1000);
+        assert_eq!(chip.reg(Register::PpmCorrection), 0);
+    }
+
+    #[test]
+    fn handle_interrupt_reports_and_keeps_packet() {
+        let (chip, mut lora) = radio();
+        chip.0.borrow_mut().fifo[..3].copy_from_slice(&[7, 8, 9]);
+        chip.set_reg(Register::RxNbBytes, 3);
+        chip.set_reg(
+            Register::IrqFlags,
+            IRQMask::RxDone.addr() | IRQMask::ValidHeader.addr() | IRQMask::CadDone.addr(),
+        );
+
+        let events = lora.handle_interrupt().unwrap();
+        assert!(events.contains(RadioEvent::RxDone));
+        assert!(events.contains(RadioEvent::CadDone { detected: false }));
+        assert!(!events.contains(RadioEvent::TxDone));
+        assert_eq!(events.iter().count(), 3);
+        assert_eq!(chip.reg(Register::IrqFlags), 0);
+
+        let packet = lora.read_packet().unwrap().unwrap();
+        assert_eq!(&packet[..], &[7, 8, 9]);
+        assert_eq!(lora.read_packet().unwrap(), None);
+
+        // a packet arriving between the interrupt and the read is reported by the next read
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        lora.handle_interrupt().unwrap();
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        assert!(lora.read_packet().unwrap().is_some());
+        assert_eq!(chip.reg(Register::IrqFlags), IRQMask::RxDone.addr());
+        assert!(lora.read_packet().unwrap().is_some());
+        assert_eq!(lora.read_packet().unwrap(), None);
+    }
+
+    #[test]
+    fn health_check_recovers_from_reset() {
+        let (chip, mut lora) = radio();
+        lora.set_spreading_factor(10).unwrap();
+        lora.set_mode(RadioMode::RxContinuous).unwrap();
+        assert_eq!(lora.check_health(&mut NoDelay).unwrap(), Health::Ok);
+
+        let configured = chip.0.borrow().regs;
+        // power-on state: everything cleared, FSK standby
+        chip.0.borrow_mut().regs = [0; 0x80];
+        chip.set_reg(Register::Version, VERSION_CHECK);
+        chip.set_reg(Register::OpMode, 0x09);
+
+        assert_eq!(
+            lora.check_health(&mut NoDelay).unwrap(),
+            Health::Recovered(Fault::UnexpectedMode(0x09))
+        );
+        for reg in [
+            Register::FrfMsb,
+            Register::FrfMid,
+            Register::FrfLsb,
+            Register::Lna,
+            Register::ModemConfig2,
+        ] {
+            assert_eq!(chip.reg(reg), configured[reg.addr() as usize]);
+        }
+        assert_eq!(chip.reg(Register::OpMode), 0x85);
+        assert_eq!(lora.check_health(&mut NoDelay).unwrap(), Health::Ok);
+    }
+
+    #[test]
+    fn recover_after_fsk_mode_restores_lora_config() {
+        let (chip, mut lora) = radio();
+        lora.set_shadow_registers(true);
+        lora.set_spreading_factor(10).unwrap();
+        lora.set_pa_ramp(PaRamp::_50us).unwrap();
+        let configured = chip.0.borrow().regs;
+
+        lora.put_in_fsk_mode().unwrap();
+        lora.set_fsk_pa_ramp(FskDataModulationShaping::GaussianBt0d5, PaRamp::_10us)
+            .unwrap();
+        chip.0.borrow_mut().regs = [0; 0x80];
+        chip.set_reg(Register::Version, VERSION_CHECK);
+
+        lora.recover(&mut NoDelay).unwrap();
+        for reg in [
+            Register::FrfMsb,
+            Register::PaRamp,
+            Register::ModemConfig1,
+            Register::ModemConfig2,
+        ] {
+            assert_eq!(chip.reg(reg), configured[reg.addr() as usize]);
+        }
+        // back in LoRa mode, reads no longer come from the FSK cache
+        assert_eq!(
+            lora.read_register(Register::ModemConfig2).unwrap(),
+            configured[Register::ModemConfig2.addr() as usize]
+        );
+    }
+
+    #[test]
+    fn shadow_registers_skip_redundant_spi() {
+        let (chip, mut lora) = radio();
+        lora.set_shadow_registers(true);
+        lora.set_spreading_factor(10).unwrap();
+        let frames = chip.0.borrow().frames;
+        lora.set_spreading_factor(10).unwrap();
+        lora.set_mode(RadioMode::Stdby).unwrap();
+        // only the operating mode, which is not shadowed, reaches the chip
+        assert_eq!(chip.0.borrow().frames, frames + 1);
+
+        chip.set_reg(Register::SyncWord, 0x34);
+        lora.sync_from_chip().unwrap();
+        let config_2 = chip.reg(Register::ModemConfig2);
+        lora.set_spreading_factor(7).unwrap();
+        assert_eq!(chip.reg(Register::ModemConfig2), (config_2 & 0x0f) | 0x70);
+        assert_eq!(lora.read_register(Register::SyncWord).unwrap(), 0x34);
+    }
+
+    #[test]
+    fn receive_session_is_not_restarted_by_polls() {
+        let (chip, mut lora) = radio();
+        assert_eq!(lora.read_packet().unwrap(), None);
+        assert!(lora.is_listening());
+        assert_eq!(chip.reg(Register::OpMode), 0x85);
+
+        // a poll which rewrote the mode would put it back
+        chip.set_reg(Register::OpMode, 0);
+        assert_eq!(lora.read_packet().unwrap(), None);
+        assert_eq!(chip.reg(Register::OpMode), 0);
+
+        lora.set_mode(RadioMode::RxSingle).unwrap();
+        chip.set_reg(Register::ModemStat, 0b0010_1111);
+        assert!(lora.modem_status().unwrap().header_valid());
+        assert_eq!(lora.read_packet().unwrap(), None);
+        assert_eq!(chip.reg(Register::OpMode), 0x86);
+
+        chip.set_reg(Register::ModemStat, 0b0001_0000);
+        assert_eq!(lora.read_packet().unwrap(), None);
+        assert_eq!(chip.reg(Register::OpMode), 0x85);
+
+        lora.stop_receive().unwrap();
+        assert!(!lora.is_listening());
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+    }
+
+    /// Delay during which the radio finishes transmitting after `remaining` milliseconds.
+    struct TxDelay {
+        chip: FakeChip,
+        remaining: u32,
+    }
+
+    impl DelayMs<u16> for TxDelay {
+        fn delay_ms(&mut self, ms: u16) {
+            self.remaining = self.remaining.saturating_sub(u32::from(ms));
+            if self.remaining == 0 && self.chip.reg(Register::OpMode) == 0x83 {
+                self.chip.set_reg(Register::OpMode, 0x81);
+                let flags = self.chip.reg(Register::IrqFlags);
+                self.chip
+                    .set_reg(Register::IrqFlags, flags | IRQMask::TxDone.addr());
+            }
+        }
+    }
+
+    /// DIO0 wired to `TxDone`.
+    struct FakeDio0(FakeChip);
+
+    impl InputPin for FakeDio0 {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Infallible> {
+            Ok(self.0.reg(Register::IrqFlags) & IRQMask::TxDone.addr() != 0)
+        }
+
+        fn is_low(&self) -> Result<bool, Infallible> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn transmit_and_wait_is_bounded_by_time_on_air() {
+        let (chip, mut lora) = radio();
+        lora.set_spreading_factor(7).unwrap();
+        lora.set_signal_bandwidth(125_000).unwrap();
+        lora.set_coding_rate_4(5).unwrap();
+        lora.set_preamble_length(8).unwrap();
+        lora.set_crc(true).unwrap();
+        assert_eq!(lora.time_on_air_us(10).unwrap(), 41_216);
+
+        let mut delay = TxDelay {
+            chip: chip.clone(),
+            remaining: 30,
+        };
+        lora.transmit_and_wait(&[0; 10], None, &mut delay).unwrap();
+        assert_eq!(chip.reg(Register::IrqFlags), 0);
+
+        let mut delay = TxDelay {
+            chip: chip.clone(),
+            remaining: 30,
+        };
+        let dio0 = FakeDio0(chip.clone());
+        lora.set_dio_mapping(LoRaDio0::CadDone).unwrap();
+        lora.transmit_and_wait_with_dio0(&[0; 10], None, &mut delay, &dio0)
+            .unwrap();
+        assert_eq!(lora.get_dio_mapping(Dio::Dio0).unwrap(), 0b10);
+        assert_eq!(chip.reg(Register::IrqFlags), 0);
+
+        // a radio which never finishes
+        let mut delay = TxDelay {
+            chip: chip.clone(),
+            remaining: u32::MAX,
+        };
+        assert!(matches!(
+            lora.transmit_and_wait(&[0; 10], None, &mut delay),
+            Err(TxTimeout)
+        ));
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+
+        // over 71 minutes on air saturates instead of wrapping to a short timeout
+        lora.set_spreading_factor(12).unwrap();
+        lora.set_signal_bandwidth(7_800).unwrap();
+        lora.set_preamble_length(0xffff).unwrap();
+        assert_eq!(lora.time_on_air_us(255).unwrap(), u32::MAX);
+    }
+
+    #[test]
+    fn split_receiver_does_not_abort_transmission() {
+        let (chip, lora) = radio();
+        let shared = crate::SharedRadio::new(lora);
+        let (sender, receiver) = shared.split();
+
+        sender.try_send(&[1, 2, 3]).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x83);
+        assert_eq!(receiver.try_recv().unwrap(), None);
+        assert_eq!(chip.reg(Register::OpMode), 0x83);
+        assert!(matches!(
+            sender.try_send(&[4]),
+            Err(crate::SplitError::Busy)
+        ));
+
+        // transmission over, the receiver starts listening
+        chip.set_reg(Register::OpMode, 0x81);
+        chip.set_reg(Register::IrqFlags, IRQMask::TxDone.addr());
+        assert_eq!(receiver.try_recv().unwrap(), None);
+        assert_eq!(chip.reg(Register::OpMode), 0x85);
+
+        // a packet which arrived before the next send is kept for the receiver
+        chip.set_reg(Register::RxNbBytes, 1);
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        assert!(matches!(
+            sender.try_send(&[4]),
+            Err(crate::SplitError::Busy)
+        ));
+        assert_eq!(chip.reg(Register::OpMode), 0x85);
+        assert_eq!(receiver.try_recv().unwrap().unwrap().len(), 1);
+        sender.try_send(&[4]).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x83);
+
+        // the sender looks for a packet without putting an idle radio in receive mode
+        chip.set_reg(Register::OpMode, 0x81);
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        assert!(matches!(
+            sender.try_send(&[5]),
+            Err(crate::SplitError::Busy)
+        ));
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+
+        // neither half waits while the radio is claimed
+        shared.with(&mut NoDelay, |_| {
+            assert!(matches!(
+                sender.try_send(&[5]),
+                Err(crate::SplitError::Busy)
+            ));
+            assert!(matches!(
+                sender.transmitting(),
+                Err(crate::SplitError::Busy)
+            ));
+            assert_eq!(receiver.try_recv().unwrap(), None);
+            assert_eq!(shared.try_with(|_| ()), None);
+        });
+    }
+
+    /// RF switch recording the paths it is set to.
+    struct FakeSwitch(Rc<RefCell<std::vec::Vec<AntennaMode>>>);
+
+    impl AntennaSwitch for FakeSwitch {
+        type Error = Infallible;
+
+        fn set_antenna(&mut self, mode: AntennaMode) -> Result<(), Infallible> {
+            self.0.borrow_mut().push(mode);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn optional_pins_and_antenna_switch() {
+        use AntennaMode::{Off, Rx, Tx};
+
+        let chip = FakeChip::new();
+        let cs = FakeCs(chip.0.clone());
+        LoRa::without_reset(chip.clone(), cs, Config::new(915), &mut NoDelay).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+
+        let switched = Rc::new(RefCell::new(std::vec::Vec::new()));
+        let pins = Pins {
+            dio0: FakeDio0(chip.clone()),
+            dio1: NoPin,
+            antenna_switch: FakeSwitch(switched.clone()),
+        };
+        let cs = FakeCs(chip.0.clone());
+        let mut lora = LoRa::with_pins(
+            chip.clone(),
+            cs,
+            NoPin,
+            pins,
+            Config::new(915),
+            &mut NoDelay,
+        )
+        .unwrap();
+        assert_eq!(*switched.borrow(), [Off, Off]);
+        switched.borrow_mut().clear();
+
+        assert_eq!(lora.read_packet().unwrap(), None);
+        let mut delay = TxDelay {
+            chip: chip.clone(),
+            remaining: 30,
+        };
+        lora.transmit_and_wait_on_dio0(&[0; 10], None, &mut delay)
+            .unwrap();
+        assert!(!lora.dio0_is_high().unwrap());
+        lora.set_mode(RadioMode::Sleep).unwrap();
+        assert_eq!(*switched.borrow(), [Rx, Off, Tx, Off, Off]);
+    }
+
+    #[test]
+    fn tx_done_interrupt_switches_the_antenna_off() {
+        use AntennaMode::{Off, Tx};
+
+        let chip = FakeChip::new();
+        let switched = Rc::new(RefCell::new(std::vec::Vec::new()));
+        let pins = Pins {
+            dio0: NoPin,
+            dio1: NoPin,
+            antenna_switch: FakeSwitch(switched.clone()),
+        };
+        let cs = FakeCs(chip.0.clone());
+        let mut lora = LoRa::with_pins(
+            chip.clone(),
+            cs,
+            NoPin,
+            pins,
+            Config::new(915),
+            &mut NoDelay,
+        )
+        .unwrap();
+        switched.borrow_mut().clear();
+
+        lora.transmit_payload(&[1, 2, 3]).unwrap();
+        assert_eq!(*switched.borrow(), [Off, Tx]);
+
+        chip.set_reg(Register::IrqFlags, IRQMask::TxDone.addr());
+        let events = lora.handle_interrupt().unwrap();
+        assert!(events.contains(RadioEvent::TxDone));
+        assert!(matches!(lora.mode, RadioMode::Stdby));
+        assert_eq!(*switched.borrow(), [Off, Tx, Off]);
+
+        // a later TxDone does not switch again
+        chip.set_reg(Register::IrqFlags, IRQMask::TxDone.addr());
+        lora.handle_interrupt().unwrap();
+        assert_eq!(*switched.borrow(), [Off, Tx, Off]);
+    }
+
+    /// Pin and RF switch failing with the name of the pin.
+    struct BrokenPin(&'static str);
+
+    impl InputPin for BrokenPin {
+        type Error = &'static str;
+
+        fn is_high(&self) -> Result<bool, &'static str> {
+            Err(self.0)
+        }
+
+        fn is_low(&self) -> Result<bool, &'static str> {
+            Err(self.0)
+        }
+    }
+
+    impl AntennaSwitch for BrokenPin {
+        type Error = &'static str;
+
+        fn set_antenna(&mut self, _mode: AntennaMode) -> Result<(), &'static str> {
+            Err(self.0)
+        }
+    }
+
+    #[test]
+    fn pin_errors_are_reported() {
+        let (_chip, mut lora) = radio();
+        assert!(matches!(
+            lora.transmit_and_wait_with_dio0(&[1], None, &mut NoDelay, &BrokenPin("dio0")),
+            Err(Dio("dio0"))
+        ));
+
+        let chip = FakeChip::new();
+        let cs = FakeCs(chip.0.clone());
+        let pins = Pins {
+            dio0: NoPin,
+            dio1: BrokenPin("dio1"),
+            antenna_switch: BrokenPin("switch"),
+        };
+        let lora = LoRa::with_pins(chip, cs, NoPin, pins, Config::new(915), &mut NoDelay);
+        assert!(matches!(lora, Err(AntennaSwitch("switch"))));
+    }
+
+    /// Clock advancing by `step` microseconds every time it is read.
+    struct FakeClock {
+        now: core::cell::Cell<u64>,
+        step: u64,
+    }
+
+    impl Clock for FakeClock {
+        fn now_us(&self) -> u64 {
+            let now = self.now.get();
+            self.now.set(now + self.step);
+            now
+        }
+    }
+
+    #[test]
+    fn schedule_transmit_starts_at_deadline() {
+        let (chip, mut lora) = radio();
+        let clock = FakeClock {
+            now: core::cell::Cell::new(1_000),
+            step: 10,
+        };
+        lora.schedule_transmit(&[1, 2, 3], 5_000, &clock).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x83);
+        assert_eq!(clock.now.get(), 5_010);
+        assert_eq!(chip.reg(Register::PayloadLength), 3);
+        assert_eq!(&chip.0.borrow().fifo[..3], &[1, 2, 3]);
+
+        chip.set_reg(Register::OpMode, 0x81);
+        assert!(matches!(
+            lora.schedule_transmit(&[1, 2, 3], 4_000, &clock),
+            Err(MissedDeadline(1_010))
+        ));
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+
+        lora.prepare_transmit(&[4]).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x82);
+        lora.start_prepared_transmit().unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x83);
+    }
+
+    #[test]
+    fn received_packets_are_timestamped() {
+        let (chip, mut lora) = radio();
+        let clock = FakeClock {
+            now: core::cell::Cell::new(1_000),
+            step: 10,
+        };
+        chip.set_reg(Register::RxNbBytes, 1);
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        lora.handle_interrupt_at(600).unwrap();
+        let packet = lora.read_packet_timestamped(&clock).unwrap().unwrap();
+        assert_eq!(packet.rx_done_us, 600);
+
+        // found by polling
+        chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+        let packet = lora.read_packet_timestamped(&clock).unwrap().unwrap();
+        assert_eq!(packet.rx_done_us, 1_010);
+        assert_eq!(lora.read_packet_timestamped(&clock).unwrap(), None);
+    }
+
+    #[test]
+    fn channel_activity_detection_is_not_transmitting() {
+        let (chip, mut lora) = radio();
+        let capabilities = lora.capabilities();
+        assert_eq!(capabilities.mtu, 255);
+        assert!(!capabilities.full_duplex && capabilities.channel_activity_detection);
+
+        lora.set_mode(RadioMode::Cad).unwrap();
+        assert_eq!(chip.reg(Register::OpMode), 0x87);
+        assert!(!lora.transmitting().unwrap());
+        lora.set_mode(RadioMode::Tx).unwrap();
+        assert!(lora.transmitting().unwrap());
+    }
+
+    #[test]
+    fn configurable_radio_validates_settings() {
+        let (chip, mut lora) = radio();
+        assert!(matches!(
+            lora.set_frequency_hz(2_400_000_000),
+            Err(Unsupported)
+        ));
+        lora.set_frequency_hz(868_100_000).unwrap();
+        assert_eq!(frf(&chip), ((868_100_000u64 << 19) / 32_000_000) as u32);
+
+        assert_eq!(lora.tx_power_range(), 2..=20);
+        assert!(matches!(lora.set_tx_power_dbm(1), Err(Unsupported)));
+        assert_eq!(lora.set_tx_power_dbm(14).unwrap(), 14);
+
+        let rate = LoRaDataRate {
+            spreading_factor: 9,
+            coding_rate: 6,
+        };
+        lora.set_data_rate(rate).unwrap();
+        assert_eq!(lora.get_spreading_factor().unwrap(), 9);
+        assert_eq!(chip.reg(Register::ModemConfig1).get_bits(1..4), 2);
+        assert!(!lora.supports_data_rate(&LoRaDataRate {
+            spreading_factor: 13,
+            ..rate
+        }));
+
+        assert!(matches!(lora.set_bandwidth(100_000), Err(Unsupported)));
+        lora.set_bandwidth(250_000).unwrap();
+        assert_eq!(lora.get_signal_bandwidth().unwrap(), 250_000);
+    }
+
+    /// DIO0 of a radio which completes whatever it was doing as soon as it is waited on.
+    #[cfg(feature = "async")]
+    struct ReactiveDio0(FakeChip);
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_1::digital::ErrorType for ReactiveDio0 {
+        type Error = Infallible;
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::digital::Wait for ReactiveDio0 {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            let chip = &self.0;
+            match chip.reg(Register::OpMode) {
+                0x83 => {
+                    chip.set_reg(Register::OpMode, 0x81);
+                    chip.set_reg(Register::IrqFlags, IRQMask::TxDone.addr());
+                }
+                0x85 => {
+                    chip.0.borrow_mut().fifo[..2].copy_from_slice(&[5, 6]);
+                    chip.set_reg(Register::RxNbBytes, 2);
+                    chip.set_reg(Register::IrqFlags, IRQMask::RxDone.addr());
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_for_high().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            self.wait_for_high().await
+        }
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::delay::DelayNs for NoDelay {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    /// DIO0 of a radio which never completes.
+    #[cfg(feature = "async")]
+    struct StuckDio0;
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_1::digital::ErrorType for StuckDio0 {
+        type Error = Infallible;
+    }
+
+    #[cfg(feature = "async")]
+    impl embedded_hal_async::digital::Wait for StuckDio0 {
+        async fn wait_for_high(&mut self) -> Result<(), Infallible> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_low(&mut self) -> Result<(), Infallible> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_rising_edge(&mut self) -> Result<(), Infallible> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_falling_edge(&mut self) -> Result<(), Infallible> {
+            core::future::pending().await
+        }
+
+        async fn wait_for_any_edge(&mut self) -> Result<(), Infallible> {
+            core::future::pending().await
+        }
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn async_driver_waits_on_dio0() {
+        use crate::radio_traits::AsyncEmbeddedRadio;
+
+        let (chip, lora) = radio();
+        let mut lora = AsyncLoRa::new(lora, ReactiveDio0(chip.clone()), NoDelay);
+        pollster::block_on(lora.transmit(&[1, 2, 3])).unwrap();
+        assert_eq!(chip.reg(Register::IrqFlags), 0);
+        assert_eq!(lora.lora().get_dio_mapping(Dio::Dio0).unwrap(), 0b01);
+
+        let packet = pollster::block_on(lora.receive()).unwrap();
+        assert_eq!(&packet[..], &[5, 6]);
+        assert_eq!(lora.lora().get_dio_mapping(Dio::Dio0).unwrap(), 0b00);
+
+        // the wrapped driver keeps the pins and the RF switch it was given
+        let switched = Rc::new(RefCell::new(std::vec::Vec::new()));
+        let pins = Pins {
+            dio0: NoPin,
+            dio1: FakeDio0(chip.clone()),
+            antenna_switch: FakeSwitch(switched.clone()),
+        };
+        let cs = FakeCs(chip.0.clone());
+        let lora = LoRa::with_pins(
+            chip.clone(),
+            cs,
+            NoPin,
+            pins,
+            Config::new(915),
+            &mut NoDelay,
+        )
+        .unwrap();
+        let mut lora = AsyncLoRa::new(lora, ReactiveDio0(chip.clone()), NoDelay);
+        switched.borrow_mut().clear();
+        pollster::block_on(lora.transmit(&[1, 2, 3])).unwrap();
+        assert_eq!(
+            *switched.borrow(),
+            [AntennaMode::Off, AntennaMode::Tx, AntennaMode::Off]
+        );
+        assert!(!lora.lora().dio1_is_high().unwrap());
+
+        // a radio which never finishes is put back in standby after its time on air
+        let (lora, _, _) = lora.decompose();
+        let mut lora = AsyncLoRa::new(lora, StuckDio0, NoDelay);
+        assert!(matches!(
+            pollster::block_on(lora.transmit(&[1, 2, 3])),
+            Err(TxTimeout)
+        ));
+        assert_eq!(chip.reg(Register::OpMode), 0x81);
+    }
+}
@@ -0,0 +1,120 @@
+//! Asynchronous driver, waiting on DIO0 instead of polling the radio over SPI.
+
+use core::future::{poll_fn, Future};
+use core::pin::pin;
+use core::task::Poll;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_async::delay::DelayNs;
+use embedded_hal_async::digital::Wait;
+use heapless::Vec;
+
+use super::dio::LoRaDio0;
+use super::pins::{AntennaSwitch, NoPin, NoSwitch};
+use super::register::{AsAddr, IRQMask, Register};
+use super::{Error, LoRa, LoRaError, RadioMode};
+use crate::radio_traits::{AsyncEmbeddedRadio, EmbeddedRadio};
+
+/// A `LoRa` driver together with the pin its DIO0 is connected to, implementing `AsyncEmbeddedRadio`.
+///
+/// DIO0 is mapped to `TxDone` while transmitting and to `RxDone` while receiving, and waited on
+/// through `IRQ`. SPI accesses are blocking and only happen between waits on the pin, so futures
+/// are never dropped halfway through a register access. Transmissions which do not complete within
+/// their time on air plus a margin, measured with `DELAY`, fail with `TxTimeout` as with
+/// `LoRa::transmit_and_wait`.
+///
+/// The wrapped driver keeps the pins it was given to `with_pins`, e.g. a DIO1 input read between
+/// waits.
+pub struct AsyncLoRa<SPI, CS, RESET, IRQ, DELAY, DIO0 = NoPin, DIO1 = NoPin, SW = NoSwitch> {
+    lora: LoRa<SPI, CS, RESET, DIO0, DIO1, SW>,
+    irq: IRQ,
+    delay: DELAY,
+}
+
+impl<SPI, CS, RESET, IRQ, DELAY, DIO0, DIO1, SW, E>
+    AsyncLoRa<SPI, CS, RESET, IRQ, DELAY, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    IRQ: Wait,
+    DELAY: DelayNs,
+    SW: AntennaSwitch,
+{
+    pub fn new(lora: LoRa<SPI, CS, RESET, DIO0, DIO1, SW>, irq: IRQ, delay: DELAY) -> Self {
+        AsyncLoRa { lora, irq, delay }
+    }
+
+    /// Returns the blocking driver, to configure the radio.
+    pub fn lora(&mut self) -> &mut LoRa<SPI, CS, RESET, DIO0, DIO1, SW> {
+        &mut self.lora
+    }
+
+    /// Return ownership of the blocking driver, the DIO0 pin and the delay.
+    pub fn decompose(self) -> (LoRa<SPI, CS, RESET, DIO0, DIO1, SW>, IRQ, DELAY) {
+        (self.lora, self.irq, self.delay)
+    }
+
+    /// Maps DIO0 to `TxDone` and starts sending the payload, returning how long to wait for it.
+    fn start_transmit(&mut self, payload: &[u8]) -> Result<u32, LoRaError<E, CS, RESET, SW>> {
+        let timeout_ms = self.lora.tx_timeout_ms(payload.len(), None)?;
+        self.lora.enable_interrupt(LoRaDio0::TxDone)?;
+        // a stale flag would raise DIO0 straight away
+        self.lora
+            .write_register(Register::IrqFlags, IRQMask::TxDone.addr())?;
+        self.lora.transmit_payload(payload)?;
+        Ok(timeout_ms)
+    }
+}
+
+impl<SPI, CS, RESET, IRQ, DELAY, DIO0, DIO1, SW, E> AsyncEmbeddedRadio
+    for AsyncLoRa<SPI, CS, RESET, IRQ, DELAY, DIO0, DIO1, SW>
+where
+    SPI: Transfer<u8, Error = E> + Write<u8, Error = E>,
+    CS: OutputPin,
+    RESET: OutputPin,
+    IRQ: Wait,
+    DELAY: DelayNs,
+    SW: AntennaSwitch,
+{
+    type Error = LoRaError<E, CS, RESET, SW, IRQ::Error>;
+
+    async fn transmit(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        let timeout_ms = self.start_transmit(payload).map_err(Error::with_dio)?;
+
+        let mut done = pin!(self.irq.wait_for_high());
+        let mut timeout = pin!(self.delay.delay_ms(timeout_ms));
+        let done = poll_fn(|cx| match done.as_mut().poll(cx) {
+            Poll::Ready(result) => Poll::Ready(Some(result)),
+            Poll::Pending => timeout.as_mut().poll(cx).map(|()| None),
+        })
+        .await;
+        match done {
+            Some(result) => result.map_err(Error::Dio)?,
+            None => {
+                self.lora
+                    .set_mode(RadioMode::Stdby)
+                    .map_err(Error::with_dio)?;
+                return Err(Error::TxTimeout);
+            }
+        }
+
+        self.lora
+            .write_register(Register::IrqFlags, IRQMask::TxDone.addr())
+            .map_err(Error::with_dio)?;
+        // the radio returns to standby on its own
+        self.lora.tx_finished().map_err(Error::with_dio)
+    }
+
+    async fn receive(&mut self) -> Result<Vec<u8, 255>, Self::Error> {
+        self.lora
+            .enable_interrupt(LoRaDio0::RxDone)
+            .map_err(Error::with_dio)?;
+        loop {
+            if let Some(packet) = self.lora.read_packet().map_err(Error::with_dio)? {
+                return Ok(packet);
+            }
+            self.irq.wait_for_high().await.map_err(Error::Dio)?;
+        }
+    }
+}
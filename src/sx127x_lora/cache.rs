@@ -0,0 +1,86 @@
+//! Copy of the configuration registers last written by the driver, used to restore the radio
+//! after it lost its configuration.
+
+use super::register::{AsAddr, Register};
+
+/// The configuration registers, in the order they are restored after a reset. Registers left out
+/// are status registers, FIFO access or written every time they are used.
+const CONFIG_REGISTERS: [Register; 26] = [
+    Register::Tcxo,
+    Register::FrfMsb,
+    Register::FrfMid,
+    Register::FrfLsb,
+    Register::PaConfig,
+    Register::PaRamp,
+    Register::Ocp,
+    Register::PaDac,
+    Register::Lna,
+    Register::FifoTxBaseAddr,
+    Register::FifoRxBaseAddr,
+    Register::IrqFlagsMask,
+    Register::ModemConfig1,
+    Register::ModemConfig2,
+    Register::ModemConfig3,
+    Register::PreambleMsb,
+    Register::PreambleLsb,
+    Register::PayloadLength,
+    Register::PpmCorrection,
+    Register::DetectionOptimize,
+    Register::DetectionThreshold,
+    Register::Invertiq,
+    Register::Invertiq2,
+    Register::SyncWord,
+    Register::DioMapping1,
+    Register::DioMapping2,
+];
+
+#[derive(Clone)]
+pub(crate) struct RegisterCache {
+    values: [u8; CONFIG_REGISTERS.len()],
+    /// One bit per entry of `CONFIG_REGISTERS` which has been written.
+    valid: u32,
+}
+
+impl RegisterCache {
+    pub(crate) fn new() -> Self {
+        RegisterCache {
+            values: [0; CONFIG_REGISTERS.len()],
+            valid: 0,
+        }
+    }
+
+    /// Returns the configuration registers which are cached.
+    pub(crate) fn registers() -> impl Iterator<Item = Register> {
+        CONFIG_REGISTERS.iter().copied()
+    }
+
+    fn index(addr: u8) -> Option<usize> {
+        CONFIG_REGISTERS.iter().position(|reg| reg.addr() == addr)
+    }
+
+    /// Returns the value last written to the register, if it is a configuration register which
+    /// has been written.
+    pub(crate) fn get(&self, addr: u8) -> Option<u8> {
+        Self::index(addr)
+            .filter(|&index| self.valid & (1 << index) != 0)
+            .map(|index| self.values[index])
+    }
+
+    /// Records a value written to a register. Registers which are not cached are ignored.
+    pub(crate) fn set(&mut self, addr: u8, value: u8) {
+        if let Some(index) = Self::index(addr) {
+            self.values[index] = value;
+            self.valid |= 1 << index;
+        }
+    }
+
+    /// Iterates over the written configuration registers and their values, in restore order.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Register, u8)> + '_ {
+        CONFIG_REGISTERS
+            .iter()
+            .zip(self.values.iter())
+            .enumerate()
+            .filter(move |(index, _)| self.valid & (1 << index) != 0)
+            .map(|(_, (&reg, &value))| (reg, value))
+    }
+}
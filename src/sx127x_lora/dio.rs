@@ -0,0 +1,277 @@
+//! Typed DIO pin mappings for the SX1276/77/78/79.
+//!
+//! Each DIO pin has its own mapping type per modem, following Table 18 (LoRa mode) and
+//! Table 29 (FSK/OOK packet mode) of the datasheet. Mappings are applied with
+//! `LoRa::set_dio_mapping`, which only touches the bits of the pin being mapped.
+
+use super::register::{IRQMask, Register};
+
+/// One of the radio's six digital I/O pins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dio {
+    Dio0,
+    Dio1,
+    Dio2,
+    Dio3,
+    Dio4,
+    Dio5,
+}
+
+impl Dio {
+    /// Returns the mapping register holding this pin's two mapping bits, and the offset of the
+    /// lowest of those bits.
+    pub(crate) fn location(self) -> (Register, usize) {
+        match self {
+            Dio::Dio0 => (Register::DioMapping1, 6),
+            Dio::Dio1 => (Register::DioMapping1, 4),
+            Dio::Dio2 => (Register::DioMapping1, 2),
+            Dio::Dio3 => (Register::DioMapping1, 0),
+            Dio::Dio4 => (Register::DioMapping2, 6),
+            Dio::Dio5 => (Register::DioMapping2, 4),
+        }
+    }
+}
+
+/// A function which can be routed to a DIO pin.
+pub trait DioMapping: Copy {
+    /// The pin this mapping applies to.
+    const PIN: Dio;
+
+    /// Returns the two-bit value written to the pin's field of `RegDioMapping1`/`RegDioMapping2`.
+    fn bits(self) -> u8;
+
+    /// Returns the value of the `MapPreambleDetect` bit of `RegDioMapping2`, if this mapping
+    /// depends on it.
+    fn map_preamble_detect(self) -> Option<bool> {
+        None
+    }
+
+    /// Returns the LoRa IRQ source signalled on the pin by this mapping, if any.
+    fn irq(self) -> Option<IRQMask> {
+        None
+    }
+}
+
+/// DIO0 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio0 {
+    RxDone = 0b00,
+    TxDone = 0b01,
+    CadDone = 0b10,
+}
+
+/// DIO1 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio1 {
+    RxTimeout = 0b00,
+    FhssChangeChannel = 0b01,
+    CadDetected = 0b10,
+}
+
+/// DIO2 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio2 {
+    FhssChangeChannel = 0b00,
+}
+
+/// DIO3 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio3 {
+    CadDone = 0b00,
+    ValidHeader = 0b01,
+    PayloadCrcError = 0b10,
+}
+
+/// DIO4 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio4 {
+    CadDetected = 0b00,
+    PllLock = 0b01,
+}
+
+/// DIO5 functions in LoRa mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaDio5 {
+    ModeReady = 0b00,
+    ClkOut = 0b01,
+}
+
+/// DIO0 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio0 {
+    /// `PayloadReady` while receiving, `PacketSent` while transmitting.
+    PayloadReadyOrPacketSent = 0b00,
+    CrcOk = 0b01,
+    TempChangeOrLowBat = 0b11,
+}
+
+/// DIO1 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio1 {
+    FifoLevel = 0b00,
+    FifoEmpty = 0b01,
+    FifoFull = 0b10,
+}
+
+/// DIO2 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio2 {
+    FifoFull = 0b00,
+    RxReady = 0b01,
+    Timeout = 0b10,
+    SyncAddress = 0b11,
+}
+
+/// DIO3 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio3 {
+    FifoEmpty = 0b00,
+    TxReady = 0b01,
+}
+
+/// DIO4 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio4 {
+    TempChangeOrLowBat,
+    PllLock,
+    Timeout,
+    /// RSSI interrupt, selected by clearing `MapPreambleDetect`.
+    Rssi,
+    /// Preamble detect interrupt, selected by setting `MapPreambleDetect`.
+    PreambleDetect,
+}
+
+/// DIO5 functions in FSK/OOK packet mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskDio5 {
+    ClkOut = 0b00,
+    PllLock = 0b01,
+    Data = 0b10,
+    ModeReady = 0b11,
+}
+
+impl DioMapping for LoRaDio0 {
+    const PIN: Dio = Dio::Dio0;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+    fn irq(self) -> Option<IRQMask> {
+        Some(match self {
+            LoRaDio0::RxDone => IRQMask::RxDone,
+            LoRaDio0::TxDone => IRQMask::TxDone,
+            LoRaDio0::CadDone => IRQMask::CadDone,
+        })
+    }
+}
+
+impl DioMapping for LoRaDio1 {
+    const PIN: Dio = Dio::Dio1;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+    fn irq(self) -> Option<IRQMask> {
+        Some(match self {
+            LoRaDio1::RxTimeout => IRQMask::RxTimeout,
+            LoRaDio1::FhssChangeChannel => IRQMask::FhssChangeChannel,
+            LoRaDio1::CadDetected => IRQMask::CadDetected,
+        })
+    }
+}
+
+impl DioMapping for LoRaDio2 {
+    const PIN: Dio = Dio::Dio2;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+    fn irq(self) -> Option<IRQMask> {
+        Some(IRQMask::FhssChangeChannel)
+    }
+}
+
+impl DioMapping for LoRaDio3 {
+    const PIN: Dio = Dio::Dio3;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+    fn irq(self) -> Option<IRQMask> {
+        Some(match self {
+            LoRaDio3::CadDone => IRQMask::CadDone,
+            LoRaDio3::ValidHeader => IRQMask::ValidHeader,
+            LoRaDio3::PayloadCrcError => IRQMask::PayloadCrcError,
+        })
+    }
+}
+
+impl DioMapping for LoRaDio4 {
+    const PIN: Dio = Dio::Dio4;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+    fn irq(self) -> Option<IRQMask> {
+        match self {
+            LoRaDio4::CadDetected => Some(IRQMask::CadDetected),
+            LoRaDio4::PllLock => None,
+        }
+    }
+}
+
+impl DioMapping for LoRaDio5 {
+    const PIN: Dio = Dio::Dio5;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+impl DioMapping for FskDio0 {
+    const PIN: Dio = Dio::Dio0;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+impl DioMapping for FskDio1 {
+    const PIN: Dio = Dio::Dio1;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+impl DioMapping for FskDio2 {
+    const PIN: Dio = Dio::Dio2;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+impl DioMapping for FskDio3 {
+    const PIN: Dio = Dio::Dio3;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+impl DioMapping for FskDio4 {
+    const PIN: Dio = Dio::Dio4;
+    fn bits(self) -> u8 {
+        match self {
+            FskDio4::TempChangeOrLowBat => 0b00,
+            FskDio4::PllLock => 0b01,
+            FskDio4::Timeout => 0b10,
+            FskDio4::Rssi | FskDio4::PreambleDetect => 0b11,
+        }
+    }
+    fn map_preamble_detect(self) -> Option<bool> {
+        match self {
+            FskDio4::Rssi => Some(false),
+            FskDio4::PreambleDetect => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl DioMapping for FskDio5 {
+    const PIN: Dio = Dio::Dio5;
+    fn bits(self) -> u8 {
+        self as u8
+    }
+}
@@ -0,0 +1,129 @@
+//! Decoding of the LoRa `RegIrqFlags` register into radio events.
+
+use super::register::{AsAddr, IRQMask};
+
+/// An event signalled by the radio through its IRQ flags.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RadioEvent {
+    /// The receive timeout of a single receive elapsed without a packet.
+    RxTimeout,
+    /// A packet has been received and is ready in the FIFO.
+    RxDone,
+    /// The payload CRC of the received packet did not match.
+    CrcError,
+    /// A valid header has been received in explicit header mode.
+    ValidHeader,
+    /// A packet has been transmitted.
+    TxDone,
+    /// Channel activity detection finished, `detected` tells whether activity was found.
+    CadDone { detected: bool },
+    /// The frequency hopping period elapsed and a new channel should be programmed.
+    FhssChangeChannel,
+}
+
+impl RadioEvent {
+    /// Returns the IRQ flags which signal this event.
+    fn flags(self) -> u8 {
+        match self {
+            RadioEvent::RxTimeout => IRQMask::RxTimeout.addr(),
+            RadioEvent::RxDone => IRQMask::RxDone.addr(),
+            RadioEvent::CrcError => IRQMask::PayloadCrcError.addr(),
+            RadioEvent::ValidHeader => IRQMask::ValidHeader.addr(),
+            RadioEvent::TxDone => IRQMask::TxDone.addr(),
+            RadioEvent::CadDone { detected: false } => IRQMask::CadDone.addr(),
+            RadioEvent::CadDone { detected: true } => {
+                IRQMask::CadDone.addr() | IRQMask::CadDetected.addr()
+            }
+            RadioEvent::FhssChangeChannel => IRQMask::FhssChangeChannel.addr(),
+        }
+    }
+}
+
+/// The set of events reported by a single read of the IRQ flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RadioEvents {
+    flags: u8,
+}
+
+impl RadioEvents {
+    /// Builds the event set from a raw `RegIrqFlags` value.
+    pub fn from_flags(flags: u8) -> Self {
+        RadioEvents { flags }
+    }
+
+    /// Returns the raw `RegIrqFlags` value.
+    pub fn flags(self) -> u8 {
+        self.flags
+    }
+
+    /// Returns true if no event was signalled.
+    pub fn is_empty(self) -> bool {
+        self.flags == 0
+    }
+
+    /// Returns true if the event was signalled. `CadDone` only matches when `detected` agrees
+    /// with the `CadDetected` flag.
+    pub fn contains(self, event: RadioEvent) -> bool {
+        match event {
+            RadioEvent::CadDone { .. } => {
+                let cad = IRQMask::CadDone.addr() | IRQMask::CadDetected.addr();
+                self.flags & cad == event.flags()
+            }
+            _ => self.flags & event.flags() != 0,
+        }
+    }
+
+    /// Iterates over the signalled events.
+    pub fn iter(self) -> RadioEventsIter {
+        RadioEventsIter {
+            events: self,
+            next: 0,
+        }
+    }
+}
+
+impl IntoIterator for RadioEvents {
+    type Item = RadioEvent;
+    type IntoIter = RadioEventsIter;
+
+    fn into_iter(self) -> RadioEventsIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the events of a `RadioEvents` set.
+#[derive(Clone, Debug)]
+pub struct RadioEventsIter {
+    events: RadioEvents,
+    next: usize,
+}
+
+impl Iterator for RadioEventsIter {
+    type Item = RadioEvent;
+
+    fn next(&mut self) -> Option<RadioEvent> {
+        const ALL: [RadioEvent; 7] = [
+            RadioEvent::RxTimeout,
+            RadioEvent::RxDone,
+            RadioEvent::CrcError,
+            RadioEvent::ValidHeader,
+            RadioEvent::TxDone,
+            RadioEvent::CadDone { detected: false },
+            RadioEvent::FhssChangeChannel,
+        ];
+
+        while let Some(&event) = ALL.get(self.next) {
+            self.next += 1;
+            let event = match event {
+                RadioEvent::CadDone { .. } => RadioEvent::CadDone {
+                    detected: self.events.flags & IRQMask::CadDetected.addr() != 0,
+                },
+                event => event,
+            };
+            if self.events.contains(event) {
+                return Some(event);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,197 @@
+//! Linux support, opening the radio through spidev and the GPIO character device.
+
+use core::convert::Infallible;
+use core::ops::{Deref, DerefMut};
+use embedded_hal::blocking::delay::DelayMs;
+use embedded_hal::spi::{Mode, Phase, Polarity};
+use heapless::Vec;
+use linux_embedded_hal::gpio_cdev::{self, Chip, LineRequestFlags};
+use linux_embedded_hal::spidev::{SpiModeFlags, SpidevOptions};
+use linux_embedded_hal::{CdevPin, Delay, Spidev};
+use std::io;
+use std::path::Path;
+
+use super::{Config, Error, LoRa, RadioMode, MODE};
+use crate::radio_traits::{Capabilities, EmbeddedRadio};
+
+/// GPIO chip of the Raspberry Pi header, used by `LoRa::open_linux`.
+pub const GPIO_CHIP: &str = "/dev/gpiochip0";
+/// SPI clock used by `LoRa::open_linux`, within the 10 MHz the SX127x supports.
+pub const SPI_SPEED_HZ: u32 = 8_000_000;
+/// Name the GPIO lines are requested under, shown by `gpioinfo`.
+const CONSUMER: &str = "sx127x_lora";
+
+/// Error of a radio opened with `LoRa::open_linux`, including reads of its DIO0 line.
+pub type LinuxError =
+    Error<io::Error, gpio_cdev::Error, gpio_cdev::Error, Infallible, gpio_cdev::Error>;
+
+/// A `LoRa` driver opened with `LoRa::open_linux`, owning the SPI device and the GPIO lines.
+///
+/// It dereferences to the `LoRa` driver. When dropped, the radio is put to sleep and the GPIO lines are released,
+/// so nothing is left behind as with the exported pins of the deprecated sysfs interface.
+pub struct LinuxLoRa {
+    lora: LoRa<Spidev, CdevPin, CdevPin>,
+    dio0: Option<CdevPin>,
+}
+
+impl LoRa<Spidev, CdevPin, CdevPin> {
+    /// Opens a radio connected to the SPI device `spi_path`, e.g. `/dev/spidev0.0`, and to the lines of the
+    /// Raspberry Pi GPIO chip numbered `cs_line`, `reset_line` and optionally `dio0_line`. The SPI device is set
+    /// up with `MODE` at `SPI_SPEED_HZ`, then the radio is reset and configured as `with_config` does.
+    ///
+    /// The driver toggles `cs_line` itself, so the SPI device is opened with `SPI_NO_CS`. `cs_line` must be a free
+    /// line rather than a chip enable of the SPI controller, such as GPIO 8 for `/dev/spidev0.0`, which the kernel
+    /// keeps for itself.
+    pub fn open_linux<P: AsRef<Path>>(
+        spi_path: P,
+        cs_line: u32,
+        reset_line: u32,
+        dio0_line: Option<u32>,
+        config: Config,
+    ) -> Result<LinuxLoRa, LinuxError> {
+        Self::open_linux_on(GPIO_CHIP, spi_path, cs_line, reset_line, dio0_line, config)
+    }
+
+    /// Opens a radio as `open_linux` does, with its lines on the GPIO chip `gpio_chip`, e.g. `/dev/gpiochip1`.
+    pub fn open_linux_on<C: AsRef<Path>, P: AsRef<Path>>(
+        gpio_chip: C,
+        spi_path: P,
+        cs_line: u32,
+        reset_line: u32,
+        dio0_line: Option<u32>,
+        config: Config,
+    ) -> Result<LinuxLoRa, LinuxError> {
+        let mut spi = Spidev::open(spi_path).map_err(Error::Spi)?;
+        let options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(SPI_SPEED_HZ)
+            .mode(spi_mode(MODE) | SpiModeFlags::SPI_NO_CS)
+            .build();
+        spi.configure(&options).map_err(Error::Spi)?;
+
+        let mut chip = Chip::new(gpio_chip).map_err(Error::CS)?;
+        let cs = request(&mut chip, cs_line, LineRequestFlags::OUTPUT, 1).map_err(Error::CS)?;
+        let reset =
+            request(&mut chip, reset_line, LineRequestFlags::OUTPUT, 1).map_err(Error::Reset)?;
+        let dio0 = dio0_line
+            .map(|line| request(&mut chip, line, LineRequestFlags::INPUT, 0))
+            .transpose()
+            .map_err(Error::Dio)?;
+
+        let lora =
+            LoRa::with_config(spi, cs, reset, config, &mut Delay).map_err(Error::with_dio)?;
+        Ok(LinuxLoRa { lora, dio0 })
+    }
+}
+
+fn request(
+    chip: &mut Chip,
+    line: u32,
+    flags: LineRequestFlags,
+    default: u8,
+) -> Result<CdevPin, gpio_cdev::Error> {
+    CdevPin::new(chip.get_line(line)?.request(flags, default, CONSUMER)?)
+}
+
+/// Returns the spidev flags of an SPI mode.
+fn spi_mode(mode: Mode) -> SpiModeFlags {
+    let mut flags = SpiModeFlags::empty();
+    if mode.polarity == Polarity::IdleHigh {
+        flags |= SpiModeFlags::SPI_CPOL;
+    }
+    if mode.phase == Phase::CaptureOnSecondTransition {
+        flags |= SpiModeFlags::SPI_CPHA;
+    }
+    flags
+}
+
+impl LinuxLoRa {
+    /// Returns the DIO0 input, if a line was given for it, e.g. for `transmit_and_wait_with_dio0`.
+    pub fn dio0(&self) -> Option<&CdevPin> {
+        self.dio0.as_ref()
+    }
+}
+
+impl Deref for LinuxLoRa {
+    type Target = LoRa<Spidev, CdevPin, CdevPin>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.lora
+    }
+}
+
+impl DerefMut for LinuxLoRa {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.lora
+    }
+}
+
+impl Drop for LinuxLoRa {
+    fn drop(&mut self) {
+        let _ = self.lora.set_mode(RadioMode::Sleep);
+    }
+}
+
+impl EmbeddedRadio for LinuxLoRa {
+    type Error = LinuxError;
+
+    fn transmit_payload(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.lora.transmit_payload(payload).map_err(Error::with_dio)
+    }
+
+    fn transmit_vectored(&mut self, payloads: &[&[u8]]) -> Result<(), Self::Error> {
+        self.lora
+            .transmit_vectored(payloads)
+            .map_err(Error::with_dio)
+    }
+
+    fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.lora
+            .transmit_payload_busy(payload)
+            .map_err(Error::with_dio)
+    }
+
+    fn transmitting(&mut self) -> Result<bool, Self::Error> {
+        self.lora.transmitting().map_err(Error::with_dio)
+    }
+
+    fn packet_pending(&mut self) -> Result<bool, Self::Error> {
+        self.lora.packet_pending().map_err(Error::with_dio)
+    }
+
+    fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, Self::Error> {
+        self.lora.read_packet().map_err(Error::with_dio)
+    }
+
+    fn read_packet_into(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, Self::Error> {
+        self.lora.read_packet_into(buffer).map_err(Error::with_dio)
+    }
+
+    fn read_packet_timeout(
+        &mut self,
+        timeout_ms: i32,
+        delay: &mut dyn DelayMs<u16>,
+    ) -> Result<Option<Vec<u8, 255>>, Self::Error> {
+        self.lora
+            .read_packet_timeout(timeout_ms, delay)
+            .map_err(Error::with_dio)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.lora.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spi_mode_flags() {
+        assert_eq!(spi_mode(MODE), SpiModeFlags::SPI_MODE_0);
+        assert_eq!(
+            spi_mode(embedded_hal::spi::MODE_3),
+            SpiModeFlags::SPI_MODE_3
+        );
+    }
+}
@@ -0,0 +1,114 @@
+//! Optional pins of a board: reset, DIO inputs and the RF switch.
+
+use core::convert::Infallible;
+use core::fmt::Debug;
+use embedded_hal::digital::v2::OutputPin;
+
+/// Stands for a pin which is not connected, e.g. the reset of a board tying NRESET to the MCU reset.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoPin;
+
+impl OutputPin for NoPin {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Path the RF switch connects the antenna to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntennaMode {
+    /// Neither path, while the radio sleeps or stands by.
+    Off,
+    /// The receiver, while receiving or detecting channel activity.
+    Rx,
+    /// The power amplifier, while transmitting.
+    Tx,
+}
+
+/// RF switch of a board, set by `LoRa::set_mode` whenever the radio changes mode. It is switched before the
+/// radio starts receiving or transmitting, and after it stops.
+pub trait AntennaSwitch {
+    type Error: Debug;
+
+    fn set_antenna(&mut self, mode: AntennaMode) -> Result<(), Self::Error>;
+}
+
+/// Boards without an RF switch.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoSwitch;
+
+impl AntennaSwitch for NoSwitch {
+    type Error = Infallible;
+
+    fn set_antenna(&mut self, _mode: AntennaMode) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// RF switch driven by an RXEN and a TXEN pin, both low when off.
+pub struct RfSwitch<RXEN, TXEN> {
+    pub rxen: RXEN,
+    pub txen: TXEN,
+}
+
+/// Error of the pins of an `RfSwitch`.
+#[derive(Debug)]
+pub enum RfSwitchError<RXEN, TXEN> {
+    Rxen(RXEN),
+    Txen(TXEN),
+}
+
+impl<RXEN, TXEN> AntennaSwitch for RfSwitch<RXEN, TXEN>
+where
+    RXEN: OutputPin,
+    TXEN: OutputPin,
+    RXEN::Error: Debug,
+    TXEN::Error: Debug,
+{
+    type Error = RfSwitchError<RXEN::Error, TXEN::Error>;
+
+    fn set_antenna(&mut self, mode: AntennaMode) -> Result<(), Self::Error> {
+        // never enable both paths, even briefly
+        match mode {
+            AntennaMode::Off => {
+                self.rxen.set_low().map_err(RfSwitchError::Rxen)?;
+                self.txen.set_low().map_err(RfSwitchError::Txen)
+            }
+            AntennaMode::Rx => {
+                self.txen.set_low().map_err(RfSwitchError::Txen)?;
+                self.rxen.set_high().map_err(RfSwitchError::Rxen)
+            }
+            AntennaMode::Tx => {
+                self.rxen.set_low().map_err(RfSwitchError::Rxen)?;
+                self.txen.set_high().map_err(RfSwitchError::Txen)
+            }
+        }
+    }
+}
+
+/// Pins of a board beside SPI, chip select and reset, given to `LoRa::with_pins`. Pins which are not connected
+/// are `NoPin`, and boards without an RF switch use `NoSwitch`.
+pub struct Pins<DIO0 = NoPin, DIO1 = NoPin, SW = NoSwitch> {
+    /// Input connected to DIO0, signalling `TxDone` and `RxDone`.
+    pub dio0: DIO0,
+    /// Input connected to DIO1, signalling `RxTimeout` and `CadDetected`.
+    pub dio1: DIO1,
+    pub antenna_switch: SW,
+}
+
+impl Pins {
+    /// No DIO input and no RF switch.
+    pub const fn none() -> Self {
+        Pins {
+            dio0: NoPin,
+            dio1: NoPin,
+            antenna_switch: NoSwitch,
+        }
+    }
+}
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
 
+use crate::radio_traits::PaSelect;
+
 #[derive(Clone, Copy)]
 pub enum Register {
     Fifo = 0x00,
@@ -15,8 +17,10 @@ pub enum Register {
     FifoTxBaseAddr = 0x0e,
     FifoRxBaseAddr = 0x0f,
     FifoRxCurrentAddr = 0x10,
+    IrqFlagsMask = 0x11,
     IrqFlags = 0x12,
     RxNbBytes = 0x13,
+    ModemStat = 0x18,
     PktSnrValue = 0x19,
     PktRssiValue = 0x1a,
     ModemConfig1 = 0x1d,
@@ -25,6 +29,7 @@ pub enum Register {
     PreambleLsb = 0x21,
     PayloadLength = 0x22,
     ModemConfig3 = 0x26,
+    PpmCorrection = 0x27,
     FreqErrorMsb = 0x28,
     FreqErrorMid = 0x29,
     FreqErrorLsb = 0x2a,
@@ -35,33 +40,57 @@ pub enum Register {
     SyncWord = 0x39,
     Invertiq2 = 0x3b,
     DioMapping1 = 0x40,
+    DioMapping2 = 0x41,
     Version = 0x42,
+    Tcxo = 0x4b,
     PaDac = 0x4d,
 }
+/// Registers which only exist in FSK/OOK mode, some of them sharing their address with a LoRa register.
 #[derive(Clone, Copy)]
-pub enum PaConfig {
-    PaBoost = 0x80,
-    PaOutputRfoPin = 0,
+pub enum FskRegister {
+    BitrateMsb = 0x02,
+    BitrateLsb = 0x03,
+    FdevMsb = 0x04,
+    FdevLsb = 0x05,
+    RxConfig = 0x0d,
 }
 
-#[derive(Clone, Copy)]
+/// The LoRa IRQ sources, as laid out in `RegIrqFlags` and `RegIrqFlagsMask`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum IRQMask {
+    CadDetected = 0x01,
+    FhssChangeChannel = 0x02,
+    CadDone = 0x04,
     TxDone = 0x08,
+    ValidHeader = 0x10,
     PayloadCrcError = 0x20,
     RxDone = 0x40,
+    RxTimeout = 0x80,
 }
 
 pub trait AsAddr {
     fn addr(self) -> u8;
 }
 
+impl AsAddr for u8 {
+    fn addr(self) -> u8 {
+        self
+    }
+}
+
 impl AsAddr for Register {
     fn addr(self) -> u8 {
         self as u8
     }
 }
 
-impl AsAddr for PaConfig {
+impl AsAddr for FskRegister {
+    fn addr(self) -> u8 {
+        self as u8
+    }
+}
+
+impl AsAddr for PaSelect {
     fn addr(self) -> u8 {
         self as u8
     }
@@ -73,16 +102,18 @@ impl AsAddr for IRQMask {
     }
 }
 
-#[derive(Clone, Copy)]
+/// Gaussian filter applied to the FSK data, the `ModulationShaping` field of `RegPaRamp`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FskDataModulationShaping {
-    None = 1,
-    GaussianBt1d0 = 2,
-    GaussianBt0d5 = 10,
-    GaussianBt0d3 = 11,
+    None = 0b00,
+    GaussianBt1d0 = 0b01,
+    GaussianBt0d5 = 0b10,
+    GaussianBt0d3 = 0b11,
 }
 
-#[derive(Clone, Copy)]
-pub enum FskRampUpRamDown {
+/// Rise and fall time of the power amplifier ramp, the `PaRamp` field of `RegPaRamp`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaRamp {
     _3d4ms = 0b000,
     _2ms = 0b0001,
     _1ms = 0b0010,
@@ -100,3 +131,32 @@ pub enum FskRampUpRamDown {
     _12us = 0b1110,
     _10us = 0b1111,
 }
+
+/// Gain of the low noise amplifier in front of the receiver.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LnaGain {
+    /// The gain is picked by the automatic gain control loop.
+    Agc,
+    /// Highest gain.
+    G1,
+    G2,
+    G3,
+    G4,
+    G5,
+    /// Lowest gain, 48 dB below `G1`.
+    G6,
+}
+
+impl LnaGain {
+    /// Returns the value of the `LnaGain` field of `RegLna`.
+    pub(crate) fn bits(self) -> u8 {
+        match self {
+            LnaGain::Agc | LnaGain::G1 => 0b001,
+            LnaGain::G2 => 0b010,
+            LnaGain::G3 => 0b011,
+            LnaGain::G4 => 0b100,
+            LnaGain::G5 => 0b101,
+            LnaGain::G6 => 0b110,
+        }
+    }
+}
@@ -0,0 +1,498 @@
+use crate::{AsyncEmbeddedRadio, EmbeddedRadio};
+use core::future::{self, Future};
+use core::pin::{pin, Pin};
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use heapless::Vec;
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::sync::futures::Notified;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+
+/// Source of the edges of a radio interrupt line, waking a `TokioRadio` up when the radio may have finished
+/// sending or receiving, e.g. the DIO0 line of a LoRa module read through the GPIO character device.
+///
+/// An edge is only a hint: the radio is always asked over SPI what happened, so an edge source may report
+/// spurious or merged edges.
+pub trait EdgeSource: Send + 'static {
+    /// Waits for the next edge.
+    fn wait_for_edge(&mut self) -> impl Future<Output = io::Result<()>> + Send;
+}
+
+/// Edge source for radios whose interrupt lines are not connected. The radio is polled every poll interval.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoEdges;
+
+impl EdgeSource for NoEdges {
+    fn wait_for_edge(&mut self) -> impl Future<Output = io::Result<()>> + Send {
+        future::pending()
+    }
+}
+
+/// Error of a `TokioRadio`.
+#[derive(Debug)]
+pub enum TokioRadioError<E> {
+    /// The radio failed.
+    Radio(E),
+    /// The edge source failed.
+    Edge(io::Error),
+}
+
+/// Asynchronous wrapper for tokio, running the blocking SPI transfers of a radio on tokio's blocking thread pool.
+///
+/// Between polls of the radio the wrapper waits for an edge from its `EdgeSource`, or for the poll interval to
+/// elapse, whichever comes first: with `NoEdges` the radio is polled at the poll interval, while with an edge
+/// source the interval is only a fallback for missed edges. The edge source is watched by a single task, spawned
+/// on the first wait, which wakes every waiting clone up. An error of the edge source ends that task and is
+/// returned by the next wait, after which the radio is polled at the poll interval.
+///
+/// The wrapper is cheap to clone, so a task can send while another one receives. Packets are not polled for while
+/// the radio is transmitting, as that would abort the transmission of a half duplex radio, and a packet which
+/// arrived before a `send` is read out of the radio before it switches to transmit. Such packets, as well as a
+/// packet read by a `recv` future which is then dropped, are kept for the next `recv`.
+///
+/// ```ignore
+/// let radio = TokioRadio::with_edges(lora, dio0_events);
+/// radio.send(b"hello").await?;
+/// let mut packets = radio.packets();
+/// while let Some(packet) = packets.next().await {
+///     println!("{:?}", packet?);
+/// }
+/// ```
+pub struct TokioRadio<R, E = NoEdges, const MTU: usize = 255> {
+    shared: Arc<Shared<R, E, MTU>>,
+    poll_interval: Duration,
+}
+
+/// State shared by the clones of a `TokioRadio`.
+struct Shared<R, E, const MTU: usize> {
+    radio: Mutex<R>,
+    /// Packets read on the blocking thread pool but not returned yet, because the `recv` future reading them was
+    /// dropped meanwhile or because a `send` was about to switch the radio to transmit.
+    pending: Mutex<VecDeque<Vec<u8, MTU>>>,
+    /// Edge source, until the first wait moves it to the edge task.
+    edge_source: Mutex<Option<E>>,
+    edges: Arc<Edges>,
+    edge_task: EdgeTask,
+}
+
+/// Edges broadcast by the edge task to every waiting clone.
+#[derive(Default)]
+struct Edges {
+    notify: Notify,
+    /// Error which ended the edge task, returned by the next wait.
+    error: Mutex<Option<io::Error>>,
+}
+
+/// Stops the edge task once the last clone is dropped.
+#[derive(Default)]
+struct EdgeTask(Mutex<Option<AbortHandle>>);
+
+impl Drop for EdgeTask {
+    fn drop(&mut self) {
+        if let Some(task) = lock(&self.0).take() {
+            task.abort();
+        }
+    }
+}
+
+impl<R, E, const MTU: usize> Clone for TokioRadio<R, E, MTU> {
+    fn clone(&self) -> Self {
+        TokioRadio {
+            shared: self.shared.clone(),
+            poll_interval: self.poll_interval,
+        }
+    }
+}
+
+impl<R, const MTU: usize> TokioRadio<R, NoEdges, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+    R::Error: Send + 'static,
+{
+    /// Wraps a radio whose interrupt lines are not connected, polling it every millisecond.
+    pub fn new(radio: R) -> Self {
+        Self::with_edges(radio, NoEdges).with_poll_interval(Duration::from_millis(1))
+    }
+}
+
+impl<R, E, const MTU: usize> TokioRadio<R, E, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+    R::Error: Send + 'static,
+    E: EdgeSource,
+{
+    /// Wraps a radio whose interrupt line is watched by `edges`, with a poll interval of 100 ms.
+    pub fn with_edges(radio: R, edges: E) -> Self {
+        TokioRadio {
+            shared: Arc::new(Shared {
+                radio: Mutex::new(radio),
+                pending: Mutex::default(),
+                edge_source: Mutex::new(Some(edges)),
+                edges: Arc::default(),
+                edge_task: EdgeTask::default(),
+            }),
+            poll_interval: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets the longest time between two polls of the radio.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Runs `f` with exclusive access to the radio on the blocking thread pool, e.g. to configure it.
+    pub async fn with<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut R) -> T + Send + 'static,
+    {
+        self.blocking(move |shared| f(&mut lock(&shared.radio)))
+            .await
+    }
+
+    /// Sends up to `MTU` bytes, completing once the packet has left the transmitter.
+    pub async fn send(&self, payload: &[u8]) -> Result<(), TokioRadioError<R::Error>> {
+        let payload = payload.to_vec();
+        self.blocking(move |shared| {
+            let mut radio = lock(&shared.radio);
+            // switching to Tx would drop a packet which already arrived
+            if radio.packet_pending()? {
+                if let Some(packet) = radio.read_packet()? {
+                    lock(&shared.pending).push_back(packet);
+                }
+            }
+            radio.transmit_payload(&payload)
+        })
+        .await
+        .map_err(TokioRadioError::Radio)?;
+        loop {
+            let mut edge = pin!(self.edge());
+            edge.as_mut().enable();
+            if !self
+                .with(|radio| radio.transmitting())
+                .await
+                .map_err(TokioRadioError::Radio)?
+            {
+                return Ok(());
+            }
+            self.wait(edge).await?;
+        }
+    }
+
+    /// Waits for the next packet and returns it.
+    pub async fn recv(&self) -> Result<Vec<u8, MTU>, TokioRadioError<R::Error>> {
+        loop {
+            if let Some(packet) = lock(&self.shared.pending).pop_front() {
+                return Ok(packet);
+            }
+            let mut edge = pin!(self.edge());
+            edge.as_mut().enable();
+            // the packet is stored on the blocking thread, where it is not lost if this future is dropped
+            let waiting = self
+                .blocking(|shared| {
+                    let mut radio = lock(&shared.radio);
+                    let mut pending = lock(&shared.pending);
+                    if pending.is_empty() && !radio.transmitting()? {
+                        pending.extend(radio.read_packet()?);
+                    }
+                    Ok(!pending.is_empty())
+                })
+                .await
+                .map_err(TokioRadioError::Radio)?;
+            if !waiting {
+                self.wait(edge).await?;
+            }
+        }
+    }
+
+    /// Returns a stream of the received packets.
+    pub fn packets(&self) -> Packets<R, E, MTU> {
+        Packets {
+            radio: self.clone(),
+            next: None,
+        }
+    }
+
+    /// Returns the radio, if no other clone of the wrapper is left.
+    pub fn into_inner(self) -> Option<R> {
+        Arc::into_inner(self.shared).map(|shared| {
+            shared
+                .radio
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+        })
+    }
+
+    /// Runs `f` on the blocking thread pool.
+    async fn blocking<T, F>(&self, f: F) -> T
+    where
+        T: Send + 'static,
+        F: FnOnce(&Shared<R, E, MTU>) -> T + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        match tokio::task::spawn_blocking(move || f(&shared)).await {
+            Ok(value) => value,
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /// Returns a future completing on the next edge, starting the edge task if this is the first wait.
+    fn edge(&self) -> Notified<'_> {
+        if let Some(source) = lock(&self.shared.edge_source).take() {
+            let task = tokio::spawn(forward_edges(source, self.shared.edges.clone()));
+            *lock(&self.shared.edge_task.0) = Some(task.abort_handle());
+        }
+        self.shared.edges.notify.notified()
+    }
+
+    /// Waits for an edge or the poll interval.
+    async fn wait(&self, edge: Pin<&mut Notified<'_>>) -> Result<(), TokioRadioError<R::Error>> {
+        let _ = tokio::time::timeout(self.poll_interval, edge).await;
+        match lock(&self.shared.edges.error).take() {
+            Some(e) => Err(TokioRadioError::Edge(e)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Wakes every waiting clone up on each edge, until the edge source fails.
+async fn forward_edges<E: EdgeSource>(mut source: E, edges: Arc<Edges>) {
+    loop {
+        let edge = source.wait_for_edge().await;
+        if let Err(e) = edge {
+            *lock(&edges.error) = Some(e);
+            edges.notify.notify_waiters();
+            return;
+        }
+        edges.notify.notify_waiters();
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<R, E, const MTU: usize> AsyncEmbeddedRadio<MTU> for TokioRadio<R, E, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+    R::Error: Send + 'static,
+    E: EdgeSource,
+{
+    type Error = TokioRadioError<R::Error>;
+
+    async fn transmit(&mut self, payload: &[u8]) -> Result<(), Self::Error> {
+        self.send(payload).await
+    }
+
+    async fn receive(&mut self) -> Result<Vec<u8, MTU>, Self::Error> {
+        self.recv().await
+    }
+}
+
+type Next<T, E> = Pin<Box<dyn Future<Output = Result<T, TokioRadioError<E>>> + Send>>;
+
+/// Stream of the packets received by a `TokioRadio`, returned by `TokioRadio::packets`. It never ends.
+pub struct Packets<R, E, const MTU: usize>
+where
+    R: EmbeddedRadio<MTU>,
+{
+    radio: TokioRadio<R, E, MTU>,
+    next: Option<Next<Vec<u8, MTU>, R::Error>>,
+}
+
+impl<R, E, const MTU: usize> Stream for Packets<R, E, MTU>
+where
+    R: EmbeddedRadio<MTU> + Send + 'static,
+    R::Error: Send + 'static,
+    E: EdgeSource,
+{
+    type Item = Result<Vec<u8, MTU>, TokioRadioError<R::Error>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let radio = self.radio.clone();
+        let next = self
+            .next
+            .get_or_insert_with(|| Box::pin(async move { radio.recv().await }));
+        let packet = next.as_mut().poll(cx);
+        if packet.is_ready() {
+            self.next = None;
+        }
+        packet.map(Some)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::{MockLora, RadioErrorKind};
+    use core::task::Waker;
+
+    /// Edges raised by the test through a channel.
+    struct FakeEdges(tokio::sync::mpsc::UnboundedReceiver<()>);
+
+    impl EdgeSource for FakeEdges {
+        async fn wait_for_edge(&mut self) -> io::Result<()> {
+            self.0.recv().await;
+            Ok(())
+        }
+    }
+
+    fn runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn tokio_send_and_stream_packets() {
+        runtime().block_on(async {
+            let mut loras = MockLora::new(2);
+            let mut remote = loras.pop().unwrap();
+            let radio = TokioRadio::new(loras.pop().unwrap());
+
+            radio.send(&[1, 2]).await.unwrap();
+            assert_eq!(&remote.read_packet().unwrap().unwrap()[..], &[1, 2]);
+
+            remote.transmit_payload(&[3]).unwrap();
+            remote.transmit_payload(&[4]).unwrap();
+            let mut packets = radio.packets();
+            for expected in [3, 4] {
+                let packet = future::poll_fn(|cx| Pin::new(&mut packets).poll_next(cx))
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(&packet[..], &[expected]);
+            }
+        });
+    }
+
+    #[test]
+    fn tokio_recv_wakes_on_edges() {
+        runtime().block_on(async {
+            let mut loras = MockLora::new(2);
+            let mut remote = loras.pop().unwrap();
+            let (edge, edges) = tokio::sync::mpsc::unbounded_channel();
+            let radio = TokioRadio::with_edges(loras.pop().unwrap(), FakeEdges(edges))
+                .with_poll_interval(Duration::from_secs(3600));
+
+            let receiver = radio.clone();
+            let received = tokio::spawn(async move { receiver.recv().await.unwrap() });
+            tokio::task::yield_now().await;
+            remote.transmit_payload(&[5]).unwrap();
+            edge.send(()).unwrap();
+            let packet = tokio::time::timeout(Duration::from_secs(5), received)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(&packet[..], &[5]);
+        });
+    }
+
+    #[test]
+    fn tokio_edges_wake_every_waiter() {
+        runtime().block_on(async {
+            let mut loras = MockLora::new(2);
+            let mut remote = loras.pop().unwrap();
+            let (edge, edges) = tokio::sync::mpsc::unbounded_channel();
+            let radio = TokioRadio::with_edges(loras.pop().unwrap(), FakeEdges(edges))
+                .with_poll_interval(Duration::from_secs(3600));
+
+            let receivers: std::vec::Vec<_> = (0..2)
+                .map(|_| {
+                    let receiver = radio.clone();
+                    tokio::spawn(async move { receiver.recv().await.unwrap()[0] })
+                })
+                .collect();
+            // both receivers found nothing and wait for an edge
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            remote.transmit_payload(&[6]).unwrap();
+            remote.transmit_payload(&[7]).unwrap();
+            edge.send(()).unwrap();
+            let mut received = std::vec::Vec::new();
+            for receiver in receivers {
+                let packet = tokio::time::timeout(Duration::from_secs(5), receiver)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                received.push(packet);
+            }
+            received.sort();
+            assert_eq!(received, [6, 7]);
+        });
+    }
+
+    #[test]
+    fn tokio_dropped_recv_keeps_its_packet() {
+        runtime().block_on(async {
+            let mut loras = MockLora::new(2);
+            let mut remote = loras.pop().unwrap();
+            let radio = TokioRadio::new(loras.pop().unwrap());
+            remote.transmit_payload(&[8]).unwrap();
+
+            // the blocking read cannot finish before the future is dropped
+            let guard = lock(&radio.shared.radio);
+            let mut receiving = Box::pin(radio.recv());
+            let mut cx = Context::from_waker(Waker::noop());
+            assert!(receiving.as_mut().poll(&mut cx).is_pending());
+            drop(receiving);
+            drop(guard);
+
+            while lock(&radio.shared.pending).is_empty() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            assert_eq!(&radio.recv().await.unwrap()[..], &[8]);
+        });
+    }
+
+    /// Half duplex radio holding a single received packet, which transmitting drops.
+    struct HalfDuplexRadio {
+        fifo: Option<Vec<u8, 255>>,
+        sent: usize,
+    }
+
+    impl EmbeddedRadio for HalfDuplexRadio {
+        type Error = RadioErrorKind;
+
+        fn transmit_payload(&mut self, _payload: &[u8]) -> Result<(), RadioErrorKind> {
+            self.fifo = None;
+            self.sent += 1;
+            Ok(())
+        }
+
+        fn transmit_payload_busy(&mut self, payload: &[u8]) -> Result<(), RadioErrorKind> {
+            self.transmit_payload(payload)
+        }
+
+        fn transmitting(&mut self) -> Result<bool, RadioErrorKind> {
+            Ok(false)
+        }
+
+        fn packet_pending(&mut self) -> Result<bool, RadioErrorKind> {
+            Ok(self.fifo.is_some())
+        }
+
+        fn read_packet(&mut self) -> Result<Option<Vec<u8, 255>>, RadioErrorKind> {
+            Ok(self.fifo.take())
+        }
+    }
+
+    #[test]
+    fn tokio_send_keeps_the_received_packet() {
+        runtime().block_on(async {
+            let radio = TokioRadio::new(HalfDuplexRadio {
+                fifo: Some(Vec::from_slice(&[9]).unwrap()),
+                sent: 0,
+            });
+            radio.send(&[1]).await.unwrap();
+            assert_eq!(radio.with(|radio| radio.sent).await, 1);
+            assert_eq!(&radio.recv().await.unwrap()[..], &[9]);
+        });
+    }
+}